@@ -0,0 +1,55 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Settings that pick between alternatives, read by name so they can be
+/// switched without recompiling like the curve files. One `name value` per
+/// line, blank lines and `#` comments are skipped.
+pub struct Choices {
+    values: HashMap<String, String>,
+}
+
+impl Choices {
+    /// No file means every setting keeps its default
+    pub fn load(path: &Path) -> Self {
+        let mut text = String::new();
+        let values = match File::open(path).and_then(|mut file| file.read_to_string(&mut text)) {
+            Ok(_) => Self::parse(&text),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => {
+                println!("Error loading {}: {}", path.display(), e);
+                HashMap::new()
+            }
+        };
+        Choices { values }
+    }
+
+    fn parse(text: &str) -> HashMap<String, String> {
+        let mut values = HashMap::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            let mut parts = line.splitn(2, char::is_whitespace);
+            if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                values.insert(name.to_owned(), value.trim().to_owned());
+            }
+        }
+        values
+    }
+
+    /// The value for `name` run through `parse`, or `default` if it's not
+    /// set or `parse` doesn't understand it
+    pub fn get<T, F: Fn(&str) -> Option<T>>(&self, name: &str, parse: F, default: T) -> T {
+        let value = match self.values.get(name) {
+            Some(value) => value,
+            None => return default,
+        };
+        match parse(value) {
+            Some(choice) => choice,
+            None => {
+                println!("Unknown {}: {}", name, value);
+                default
+            }
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use std::cmp::{min, max};
+
+use cgmath::{vec2, Vector2};
+use enigo::Enigo;
+
+/// One physical screen, in global desktop pixel coordinates
+#[derive(Clone, Debug)]
+pub struct Monitor {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Monitor {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Self {
+        Monitor { x, y, width, height }
+    }
+
+    /// The primary display as reported by the OS, with its origin at 0,0
+    pub fn main_display() -> Self {
+        let (width, height) = Enigo::main_display_size();
+        Monitor::new(0, 0, width as i32, height as i32)
+    }
+
+    pub fn contains(&self, pt: Vector2<i32>) -> bool {
+        pt.x >= self.x && pt.x < self.x + self.width && pt.y >= self.y &&
+        pt.y < self.y + self.height
+    }
+
+    /// Closest point to `pt` that lies on this monitor
+    pub fn clamp(&self, pt: Vector2<i32>) -> Vector2<i32> {
+        vec2(max(self.x, min(self.x + self.width - 1, pt.x)),
+             max(self.y, min(self.y + self.height - 1, pt.y)))
+    }
+
+    fn distance2(&self, pt: Vector2<i32>) -> i64 {
+        let diff = self.clamp(pt) - pt;
+        (diff.x as i64) * (diff.x as i64) + (diff.y as i64) * (diff.y as i64)
+    }
+}
+
+/// What pointing does differently inside a `ScreenRegion`
+#[derive(Clone, Debug, Default)]
+pub struct RegionBehaviour {
+    /// don't start throws while looking into the region
+    pub no_throw: bool,
+    /// degrees of visual angle, replaces the jump size while looking into the region
+    pub min_jump: Option<f32>,
+    /// keep the cursor in the region once it's there, only throws leave it
+    pub confine: bool,
+    /// gain of head movement while the cursor is in the region
+    pub sticky_gain: Option<f32>,
+}
+
+/// A rectangle in global desktop pixels with its own pointing behaviour,
+/// e.g. a taskbar, a screen edge or an application's toolbar
+#[derive(Clone, Debug)]
+pub struct ScreenRegion {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub behaviour: RegionBehaviour,
+}
+
+impl ScreenRegion {
+    pub fn new(x: i32, y: i32, width: i32, height: i32, behaviour: RegionBehaviour) -> Self {
+        ScreenRegion {
+            x,
+            y,
+            width,
+            height,
+            behaviour,
+        }
+    }
+
+    /// Strips `size` pixels wide along the inside of each edge of a monitor
+    pub fn edges(monitor: &Monitor, size: i32, behaviour: RegionBehaviour) -> Vec<ScreenRegion> {
+        let (x, y, w, h) = (monitor.x, monitor.y, monitor.width, monitor.height);
+        vec![ScreenRegion::new(x, y, w, size, behaviour.clone()),
+             ScreenRegion::new(x, y + h - size, w, size, behaviour.clone()),
+             ScreenRegion::new(x, y, size, h, behaviour.clone()),
+             ScreenRegion::new(x + w - size, y, size, h, behaviour)]
+    }
+
+    pub fn contains(&self, pt: Vector2<i32>) -> bool {
+        pt.x >= self.x && pt.x < self.x + self.width && pt.y >= self.y &&
+        pt.y < self.y + self.height
+    }
+
+    /// Closest point to `pt` inside the region
+    pub fn clamp(&self, pt: Vector2<i32>) -> Vector2<i32> {
+        vec2(max(self.x, min(self.x + self.width - 1, pt.x)),
+             max(self.y, min(self.y + self.height - 1, pt.y)))
+    }
+}
+
+/// The arrangement of monitors making up the desktop. The eye tracker
+/// reports gaze normalized to the monitor it is attached to.
+#[derive(Clone, Debug)]
+pub struct DisplayLayout {
+    pub monitors: Vec<Monitor>,
+    pub tracker_monitor: usize,
+    /// where regions overlap the first one listed wins
+    pub regions: Vec<ScreenRegion>,
+}
+
+impl DisplayLayout {
+    pub fn tracker(&self) -> &Monitor {
+        &self.monitors[self.tracker_monitor]
+    }
+
+    /// Map a normalized gaze point on the tracker's monitor to global desktop pixels
+    pub fn gaze_to_global(&self, gaze: Vector2<f32>) -> Vector2<f32> {
+        let mon = self.tracker();
+        vec2(mon.x as f32 + gaze.x * (mon.width as f32),
+             mon.y as f32 + gaze.y * (mon.height as f32))
+    }
+
+    pub fn monitor_at(&self, pt: Vector2<i32>) -> Option<&Monitor> {
+        self.monitors.iter().find(|mon| mon.contains(pt))
+    }
+
+    pub fn region_at(&self, pt: Vector2<i32>) -> Option<&ScreenRegion> {
+        self.regions.iter().find(|region| region.contains(pt))
+    }
+
+    /// Keep a point on the desktop. Points on any monitor are left alone so the
+    /// cursor can move freely between screens, points in the gaps or outside
+    /// are moved to the nearest edge of the closest monitor.
+    pub fn confine(&self, pt: Vector2<i32>) -> Vector2<i32> {
+        if self.monitor_at(pt).is_some() {
+            return pt;
+        }
+        self.monitors
+            .iter()
+            .min_by_key(|mon| mon.distance2(pt))
+            .map(|mon| mon.clamp(pt))
+            .unwrap_or(pt)
+    }
+}
+
+/// Physical size of the tracker's screen and how far the user sits from it,
+/// used to convert parameters given in real units into pixels.
+#[derive(Clone, Debug)]
+pub struct ScreenGeometry {
+    pub px_per_mm: f32,
+    pub viewing_distance_mm: f32,
+}
+
+impl ScreenGeometry {
+    /// Assumes square pixels, so only the physical width is needed
+    pub fn new(monitor: &Monitor, width_mm: f32, viewing_distance_mm: f32) -> Self {
+        ScreenGeometry {
+            px_per_mm: monitor.width as f32 / width_mm,
+            viewing_distance_mm,
+        }
+    }
+
+    pub fn mm_to_px(&self, mm: f32) -> f32 {
+        mm * self.px_per_mm
+    }
+
+    /// Size on screen subtended by a visual angle, measured at the screen centre
+    pub fn deg_to_mm(&self, deg: f32) -> f32 {
+        2.0 * self.viewing_distance_mm * (deg.to_radians() / 2.0).tan()
+    }
+
+    pub fn deg_to_px(&self, deg: f32) -> f32 {
+        self.mm_to_px(self.deg_to_mm(deg))
+    }
+
+    /// Visual angle subtended by a distance on screen, the inverse of `deg_to_px`
+    pub fn px_to_deg(&self, px: f32) -> f32 {
+        let mm = px / self.px_per_mm;
+        2.0 * (mm / (2.0 * self.viewing_distance_mm)).atan().to_degrees()
+    }
+}
@@ -0,0 +1,257 @@
+use cgmath::Vector3;
+
+use transforms::ClickType;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gesture {
+    /// pitch down and back up
+    Nod,
+    /// yaw back and forth
+    Shake,
+    /// roll to one side and back
+    Tilt,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GestureAction {
+    Click(ClickType),
+    ToggleScroll,
+    ToggleZoom,
+    NextPointingMode,
+    Pause,
+    Recenter,
+}
+
+impl GestureAction {
+    /// `click <click type>`, `toggle_scroll`, `toggle_zoom`,
+    /// `next_pointing_mode`, `pause` or `recenter`
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut words = text.split_whitespace();
+        let action = match words.next()? {
+            "click" => GestureAction::Click(ClickType::parse(words.next()?)?),
+            "toggle_scroll" => GestureAction::ToggleScroll,
+            "toggle_zoom" => GestureAction::ToggleZoom,
+            "next_pointing_mode" => GestureAction::NextPointingMode,
+            "pause" => GestureAction::Pause,
+            "recenter" => GestureAction::Recenter,
+            _ => return None,
+        };
+        if words.next().is_some() {
+            return None;
+        }
+        Some(action)
+    }
+}
+
+#[derive(Clone)]
+pub struct GestureBindings {
+    pub nod: Option<GestureAction>,
+    pub shake: Option<GestureAction>,
+    pub tilt: Option<GestureAction>,
+}
+
+impl GestureBindings {
+    pub fn any(&self) -> bool {
+        self.nod.is_some() || self.shake.is_some() || self.tilt.is_some()
+    }
+
+    pub fn action_for(&self, gesture: Gesture) -> Option<GestureAction> {
+        match gesture {
+            Gesture::Nod => self.nod,
+            Gesture::Shake => self.shake,
+            Gesture::Tilt => self.tilt,
+        }
+    }
+}
+
+/// Angles in degrees and times in seconds
+#[derive(Clone)]
+pub struct GestureParams {
+    /// angular speed a stroke has to start with, slower movement never arms
+    pub arm_speed: f32,
+    /// how far each stroke has to travel before it reverses
+    pub min_amplitude: f32,
+    /// all strokes of a gesture have to happen within this time
+    pub window: f32,
+    /// ignore new gestures for this long after one is recognized
+    pub cooldown: f32,
+}
+
+/// Counts direction reversals on one axis, with `min_amplitude` of hysteresis
+#[derive(Default)]
+struct ZigZag {
+    extreme: f32,
+    dir: f32,
+    strokes: usize,
+    started: f32,
+}
+
+impl ZigZag {
+    fn update(&mut self, value: f32, speed: f32, time: f32, params: &GestureParams) {
+        if self.dir != 0.0 && time - self.started > params.window {
+            self.dir = 0.0;
+            self.strokes = 0;
+            self.extreme = value;
+        }
+
+        if self.dir == 0.0 {
+            let moved = value - self.extreme;
+            if speed.abs() < params.arm_speed {
+                self.extreme = value;
+            } else if moved.abs() >= params.min_amplitude {
+                self.dir = moved.signum();
+                self.strokes = 1;
+                self.started = time;
+                self.extreme = value;
+            }
+        } else if (value - self.extreme) * self.dir > 0.0 {
+            self.extreme = value;
+        } else if (self.extreme - value) * self.dir >= params.min_amplitude {
+            self.dir = -self.dir;
+            self.strokes += 1;
+            self.extreme = value;
+        }
+    }
+
+    fn reset(&mut self, value: f32) {
+        self.dir = 0.0;
+        self.strokes = 0;
+        self.extreme = value;
+    }
+}
+
+/// Recognizes nods, shakes and tilts on the filtered head pose, which is
+/// (yaw, pitch, roll) in degrees.
+pub struct GestureRecognizer {
+    pub params: GestureParams,
+    time: f32,
+    cooldown_left: f32,
+    last_pose: Option<Vector3<f32>>,
+    rest_pose: Option<Vector3<f32>>,
+    at_rest: bool,
+    yaw: ZigZag,
+    pitch: ZigZag,
+    roll: ZigZag,
+}
+
+impl GestureRecognizer {
+    pub fn new(params: GestureParams) -> Self {
+        GestureRecognizer {
+            params,
+            time: 0.0,
+            cooldown_left: 0.0,
+            last_pose: None,
+            rest_pose: None,
+            at_rest: true,
+            yaw: ZigZag::default(),
+            pitch: ZigZag::default(),
+            roll: ZigZag::default(),
+        }
+    }
+
+    pub fn update(&mut self, pose: Vector3<f32>, dt: f32) -> Option<Gesture> {
+        self.time += dt;
+        self.cooldown_left = (self.cooldown_left - dt).max(0.0);
+        let vel = match self.last_pose {
+            Some(last) if dt > 0.0 => (pose - last) / dt,
+            _ => Vector3::new(0.0, 0.0, 0.0),
+        };
+        self.last_pose = Some(pose);
+
+        self.yaw.update(pose.x, vel.x, self.time, &self.params);
+        self.pitch.update(pose.y, vel.y, self.time, &self.params);
+        self.roll.update(pose.z, vel.z, self.time, &self.params);
+        let arm_speed = self.params.arm_speed;
+        self.at_rest = !self.armed() && vel.x.abs() < arm_speed && vel.y.abs() < arm_speed &&
+                       vel.z.abs() < arm_speed;
+        if self.at_rest {
+            self.rest_pose = Some(pose);
+        }
+
+        let gesture = if self.yaw.strokes >= 3 && self.pitch.strokes <= 1 {
+            Some(Gesture::Shake)
+        } else if self.pitch.strokes >= 2 && self.yaw.strokes <= 1 {
+            Some(Gesture::Nod)
+        } else if self.roll.strokes >= 2 {
+            Some(Gesture::Tilt)
+        } else {
+            None
+        };
+
+        if gesture.is_some() {
+            self.yaw.reset(pose.x);
+            self.pitch.reset(pose.y);
+            self.roll.reset(pose.z);
+            if self.cooldown_left > 0.0 {
+                return None;
+            }
+            self.cooldown_left = self.params.cooldown;
+        }
+        gesture
+    }
+
+    fn armed(&self) -> bool {
+        self.yaw.dir != 0.0 || self.pitch.dir != 0.0 || self.roll.dir != 0.0
+    }
+
+    /// True once a stroke has reversed within the window, so the movement is
+    /// likely a gesture rather than a fast turn in one direction
+    pub fn reversed(&self) -> bool {
+        self.yaw.strokes >= 2 || self.pitch.strokes >= 2 || self.roll.strokes >= 2
+    }
+
+    /// True when the head moved slower than `arm_speed` on the last update,
+    /// so it isn't starting a gesture either
+    pub fn at_rest(&self) -> bool {
+        self.at_rest
+    }
+
+    /// The pose the head was resting in before the current or last gesture,
+    /// rather than wherever the last stroke left it
+    pub fn rest_pose(&self) -> Option<Vector3<f32>> {
+        self.rest_pose
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn recognizer() -> GestureRecognizer {
+        GestureRecognizer::new(GestureParams {
+            arm_speed: 40.0,
+            min_amplitude: 6.0,
+            window: 0.6,
+            cooldown: 1.0,
+        })
+    }
+
+    #[test]
+    fn fast_turn_in_one_direction_is_not_held() {
+        let mut gestures = recognizer();
+        let dt = 0.01;
+        for i in 0..50 {
+            let yaw = i as f32 * 100.0 * dt;
+            assert_eq!(gestures.update(Vector3::new(yaw, 0.0, 0.0), dt), None);
+            assert!(!gestures.reversed());
+        }
+    }
+
+    #[test]
+    fn shake_is_held_after_the_first_reversal() {
+        let mut gestures = recognizer();
+        let dt = 0.01;
+        let mut yaw = 0.0;
+        let mut gesture = None;
+        for &(dir, steps) in &[(1.0, 10), (-1.0, 20), (1.0, 20)] {
+            for _ in 0..steps {
+                yaw += dir * 100.0 * dt;
+                gesture = gesture.or(gestures.update(Vector3::new(yaw, 0.0, 0.0), dt));
+                if dir < 0.0 && yaw < 0.0 && gesture.is_none() {
+                    assert!(gestures.reversed());
+                }
+            }
+        }
+        assert_eq!(gesture, Some(Gesture::Shake));
+    }
+}
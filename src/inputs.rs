@@ -2,9 +2,17 @@ use std::sync::mpsc;
 use std::thread;
 
 pub enum Input {
-    LinuxTrackHead { yaw: f32, pitch: f32 },
+    LinuxTrackHead { yaw: f32, pitch: f32, roll: f32 },
+    /// timestamps are the tracker's, in microseconds
     #[cfg(feature = "tobii-sys")]
-    TobiiGaze { x: f32, y: f32 },
+    TobiiGaze { x: f32, y: f32, timestamp_us: i64 },
+    #[cfg(feature = "tobii-sys")]
+    TobiiGazeLost { timestamp_us: i64 },
+    /// whether the tracker could see each eye
+    #[cfg(feature = "tobii-sys")]
+    TobiiEyes { left: bool, right: bool },
+    #[cfg(target_os = "linux")]
+    ScrollKey { pressed: bool },
     Shutdown,
 }
 
@@ -52,7 +60,9 @@ impl InputPool {
 impl Drop for InputPool {
     fn drop(&mut self) {
         for thread in &self.threads {
-            thread.inbox.send(InputAction::Shutdown).unwrap();
+            // inputs that failed to start have already returned and dropped
+            // their inbox, the others still need to hear about it
+            let _ = thread.inbox.send(InputAction::Shutdown);
         }
 
         for thread in &mut self.threads {
@@ -0,0 +1,30 @@
+use std::os::raw::c_int;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::Duration;
+
+// the same on Linux and macOS
+const SIGINT: c_int = 2;
+const SIGTERM: c_int = 15;
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" {
+    fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+}
+
+extern "C" fn on_interrupt(_signal: c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Blocks until Ctrl-C or a kill, so the inputs get shut down in order and
+/// the pipeline saves its state on the way out
+pub fn wait() {
+    unsafe {
+        signal(SIGINT, on_interrupt);
+        signal(SIGTERM, on_interrupt);
+    }
+    while !INTERRUPTED.load(Ordering::SeqCst) {
+        thread::sleep(Duration::from_millis(100));
+    }
+}
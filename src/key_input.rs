@@ -0,0 +1,88 @@
+use std::fs::OpenOptions;
+use std::io::{self, Read};
+use std::mem;
+use std::os::raw::{c_int, c_long, c_ulong};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::slice;
+use std::sync::mpsc::{SyncSender, Receiver};
+use std::thread;
+use std::time::Duration;
+
+use inputs::{Input, InputAction};
+
+extern "C" {
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+}
+
+// from fcntl.h and linux/input.h
+const O_NONBLOCK: c_int = 0o4000;
+const EVIOCGRAB: c_ulong = 0x4004_4590;
+const EV_KEY: u16 = 0x01;
+
+#[repr(C)]
+struct InputEvent {
+    tv_sec: c_long,
+    tv_usec: c_long,
+    kind: u16,
+    code: u16,
+    value: i32,
+}
+
+/// A key on an evdev device that switches to scrolling while it's held
+#[derive(Clone, Debug)]
+pub struct KeyParams {
+    /// e.g. /dev/input/by-id/usb-...-event-kbd
+    pub device: PathBuf,
+    /// from linux/input-event-codes.h, e.g. 183 for KEY_F13
+    pub code: u16,
+    /// take the device for ourselves so nothing else sees its keys, only for
+    /// devices like foot pedals that have no other keys on them
+    pub grab: bool,
+}
+
+fn read_events(params: &KeyParams,
+               output: &SyncSender<Input>,
+               inbox: &Receiver<InputAction>)
+               -> io::Result<()> {
+    let mut file = OpenOptions::new().read(true).custom_flags(O_NONBLOCK).open(&params.device)?;
+    if params.grab && unsafe { ioctl(file.as_raw_fd(), EVIOCGRAB, 1 as c_int) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    let mut event: InputEvent = unsafe { mem::zeroed() };
+    loop {
+        if let Ok(InputAction::Shutdown) = inbox.try_recv() {
+            return Ok(());
+        }
+        let read = {
+            let bytes = unsafe {
+                slice::from_raw_parts_mut(&mut event as *mut InputEvent as *mut u8,
+                                          mem::size_of::<InputEvent>())
+            };
+            file.read_exact(bytes)
+        };
+        match read {
+            Ok(()) => {}
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(10));
+                continue;
+            }
+            Err(e) => return Err(e),
+        }
+        // 2 is auto repeat
+        if event.kind == EV_KEY && event.code == params.code && event.value != 2 {
+            output
+                .send(Input::ScrollKey { pressed: event.value == 1 })
+                .expect("shutdown should come before channel close");
+        }
+    }
+}
+
+pub fn listen(params: KeyParams, output: SyncSender<Input>, inbox: Receiver<InputAction>) {
+    if let Err(e) = read_events(&params, &output, &inbox) {
+        println!("Error reading {}: {}", params.device.display(), e);
+        // wait to be shut down like the other inputs
+        let _ = inbox.recv();
+    }
+}
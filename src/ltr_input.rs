@@ -51,6 +51,7 @@ unsafe fn input_loop(output: SyncSender<Input>, inbox: Receiver<InputAction>) {
         let input = Input::LinuxTrackHead {
             yaw: pose.raw_yaw,
             pitch: pose.raw_pitch,
+            roll: pose.raw_roll,
         };
         output
             .send(input)
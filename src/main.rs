@@ -11,32 +11,65 @@ extern crate glium;
 #[cfg(feature = "viz-2d")]
 extern crate cocoa;
 #[cfg(feature = "viz-2d")]
+#[macro_use]
 extern crate objc;
 
+mod choices;
+mod display;
+mod gestures;
 mod inputs;
+#[cfg(not(feature = "viz-2d"))]
+mod interrupt;
 mod ltr_input;
+mod output;
+mod snapshot;
+mod targets;
 #[cfg(feature = "tobii")]
 mod tobii_input;
+mod transfer;
 mod transforms;
+#[cfg(target_os = "linux")]
+mod key_input;
+#[cfg(target_os = "linux")]
+mod uinput_output;
 
 #[cfg(feature = "viz-2d")]
 mod viz_2d;
 #[cfg(feature = "viz-2d")]
-use viz_2d::{DebugSender, DebugWindow, DebugFrame, DebugPoint};
+use viz_2d::{DebugSender, DebugWindow, DebugFrame, DebugPoint, Lens};
 #[cfg(not(feature = "viz-2d"))]
 struct DebugSender();
 
-use cgmath::{vec2, Vector2};
-use enigo::{Enigo, MouseControllable};
+use cgmath::{vec2, vec3, Vector2};
 
 use std::sync::mpsc::Receiver;
 use std::time::Instant;
+use std::io;
 use std::mem;
-use std::cmp::{min, max};
+use std::path::Path;
 use std::thread;
 
+use choices::Choices;
+use display::{DisplayLayout, Monitor, RegionBehaviour, ScreenGeometry, ScreenRegion};
+use gestures::*;
 use inputs::{InputPool, Input};
+use output::{EnigoOutput, OutputBackend, PointerOutput, RelativeOutput};
+use snapshot::{load_snapshot, save_snapshot};
+use targets::*;
+use transfer::*;
 use transforms::*;
+#[cfg(target_os = "linux")]
+use key_input::KeyParams;
+#[cfg(target_os = "linux")]
+use uinput_output::UinputOutput;
+
+/// Seconds between two tracker timestamps, which unlike our own clock don't
+/// include delays getting the samples to us
+fn calc_tracker_dt(time: f64, last_time: &mut Option<f64>) -> f32 {
+    let dt = last_time.map_or(0.0, |last| time - last);
+    *last_time = Some(time);
+    dt as f32
+}
 
 fn calc_dt(tick: Instant, last_tick: &mut Instant) -> f32 {
     let dur = tick.duration_since(*last_tick);
@@ -45,91 +78,588 @@ fn calc_dt(tick: Instant, last_tick: &mut Instant) -> f32 {
     dt
 }
 
+/// Loads a transfer function from `path` if it exists, otherwise uses `default`
+fn transfer_config(path: &str, default: &Acceleration) -> Box<dyn TransferFunction> {
+    match load_transfer(Path::new(path)) {
+        Ok(curve) => curve,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => Box::new(default.clone()),
+        Err(e) => {
+            println!("Error loading {}: {}", path, e);
+            Box::new(default.clone())
+        }
+    }
+}
+
+/// Click at `at`, letting the state machine stabilize the cursor around it
+fn perform_click(output: &mut dyn PointerOutput,
+                 clicker: &mut ClickStateMachine,
+                 click: ClickType,
+                 at: Vector2<i32>) {
+    output.move_to(at);
+    for action in clicker.click(click, at) {
+        output.button(action);
+    }
+}
+
+fn pointer_output(backend: OutputBackend, layout: &DisplayLayout) -> Box<dyn PointerOutput> {
+    match backend {
+        OutputBackend::Enigo => Box::new(EnigoOutput::new()),
+        OutputBackend::EnigoRelative => {
+            // games usually keep the pointer in the middle of the screen
+            let tracker = layout.tracker();
+            Box::new(RelativeOutput::new(vec2(tracker.x + tracker.width / 2,
+                                              tracker.y + tracker.height / 2)))
+        }
+        #[cfg(target_os = "linux")]
+        OutputBackend::Uinput(mode) => {
+            match UinputOutput::new(mode, layout) {
+                Ok(uinput) => {
+                    match uinput.event_path() {
+                        Ok(path) => println!("Created uinput device {}", path.display()),
+                        Err(e) => println!("Created uinput device, can't find its node: {}", e),
+                    }
+                    Box::new(uinput)
+                }
+                Err(e) => {
+                    println!("Error creating uinput device, falling back to enigo: {}", e);
+                    Box::new(EnigoOutput::new())
+                }
+            }
+        }
+    }
+}
+
+fn target_provider(file: &str, socket: Option<&str>) -> Option<Box<dyn TargetProvider>> {
+    if Path::new(file).exists() {
+        match StaticTargets::load(Path::new(file)) {
+            Ok(targets) => return Some(Box::new(targets)),
+            Err(e) => println!("Error loading {}: {}", file, e),
+        }
+    }
+    let path = match socket.map(runtime_socket_path) {
+        Some(Some(path)) => path,
+        Some(None) => {
+            println!("Not listening for targets, XDG_RUNTIME_DIR isn't set");
+            return None;
+        }
+        None => return None,
+    };
+    match SocketTargets::listen(&path) {
+        Ok(targets) => Some(Box::new(targets)),
+        Err(e) => {
+            println!("Error listening on {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
 fn run_pipeline(rx: Receiver<Input>, debug: DebugSender) {
     // configuration
-    let accel = Acceleration {
-        cd_min: 8.0, // min gain
-        cd_max: 65.0, // max gain
-        v_min: 0.0004, // input velocity lower bound
-        v_max: 0.0025, // input velocity upper bound
-        lambda: 1000.0, // slope of curve at inflection point
+    // monitors in global desktop coordinates, the tracker is on `tracker_monitor`
+    // e.g. add Monitor::new(main_monitor.width, 0, 1920, 1080) for a screen to the right
+    let main_monitor = Monitor::main_display();
+    // regions are checked at the gaze point for throws and jump size, and at
+    // the cursor for confining and stickiness
+    let edge_behaviour = RegionBehaviour {
+        // the tracker is least accurate near the edges so throws land badly there
+        min_jump: Some(4.0), // degrees of visual angle
+        ..RegionBehaviour::default()
+    };
+    let display_layout = DisplayLayout {
+        monitors: vec![main_monitor.clone()],
+        tracker_monitor: 0,
+        // e.g. ScreenRegion::new(0, 1040, 1920, 40, RegionBehaviour { sticky_gain: Some(0.5), ..RegionBehaviour::default() })
+        regions: ScreenRegion::edges(&main_monitor, 40, edge_behaviour), // pixels
+    };
+    let screen = ScreenGeometry::new(display_layout.tracker(),
+                                     600.0, // screen width in mm
+                                     650.0 /* viewing distance in mm */);
+    let sigmoid = Acceleration {
+        cd_min: 2.0, // min gain, mm per degree
+        cd_max: 16.0, // max gain, mm per degree
+        v_min: 6.0, // input velocity lower bound, degrees per second
+        v_max: 36.0, // input velocity upper bound, degrees per second
+        lambda: 0.07, // slope of curve at inflection point
         ratio: 0.7, // where inflection lies between v_min and v_max
     };
+    // per axis curves can be swapped without recompiling, see `parse_transfer`
+    let head_transfer_x = transfer_config("head_x.curve", &sigmoid);
+    let head_transfer_y = transfer_config("head_y.curve", &sigmoid);
+    // negative y is up, replaced by the result of calibration when enabled
+    let mut head_gain = HeadGain {
+        x: 1.0,
+        up: 1.0,
+        down: 1.0,
+    };
+    // settings that pick between alternatives can be overridden here by name,
+    // e.g. a `throw_style warp` line
+    let choices = Choices::load(Path::new("choices.txt"));
+    // head movement scrolls instead of pointing while the scroll key set up
+    // in `main` is held, a gesture toggles it, or after a scroll dwell
+    let scroll_accel = Acceleration {
+        cd_min: 0.3, // min gain, lines per degree
+        cd_max: 3.0, // max gain, lines per degree
+        v_min: 4.0, // input velocity lower bound, degrees per second
+        v_max: 30.0, // input velocity upper bound, degrees per second
+        lambda: 0.1, // slope of curve at inflection point
+        ratio: 0.5, // where inflection lies between v_min and v_max
+    };
+    let scroll_dead_zone = 1.5; // degrees per second
+    let button_params = ButtonParams {
+        press_freeze: 0.3, // seconds
+        drag_threshold: 40.0, // pixels
+        double_click_time: 0.5, // seconds, match the OS setting
+        double_click_radius: 4.0, // pixels, match the OS setting
+        drag_lock: false,
+    };
+    let click_params = ClickParams {
+        left: button_params.clone(),
+        right: button_params.clone(),
+        middle: button_params,
+    };
+    // a magnified view around the gaze point with finer head control inside
+    // it, the view is drawn by the viz-2d overlay
+    let zoom_params = ZoomParams {
+        magnification: 4.0, // of the view, also divides head movement
+        radius: 1.0, // degrees of visual angle
+        after_throw: false,
+    };
+    let use_dwell_click = false;
+    let dwell_params = DwellParams {
+        radius: 0.75, // degrees of visual angle
+        dwell_time: 1.0, // seconds
+        cooldown: 0.5, // seconds
+        action: choices.get("dwell_action", DwellAction::parse, DwellAction::Click(ClickType::Left)),
+    };
+    let scroll_exit_time = dwell_params.dwell_time;
+    let blink_params = BlinkParams {
+        min_duration: 0.4, // seconds, natural blinks are shorter
+        max_duration: 1.5, // seconds, longer is looking away
+        wink_tolerance: 0.05, // seconds
+    };
+    let blink_bindings = BlinkBindings {
+        long_blink: None,
+        left_wink: None,
+        right_wink: None, // e.g. Some(ClickType::Right)
+    };
+    let gesture_params = GestureParams {
+        arm_speed: 40.0, // degrees per second
+        min_amplitude: 6.0, // degrees
+        window: 0.6, // seconds
+        cooldown: 1.0, // seconds
+    };
+    // e.g. `nod click left`, `shake pause` or `tilt toggle_scroll` in choices.txt
+    let gesture_binding = |name| {
+        choices.get(name, |text| GestureAction::parse(text).map(Some), None)
+    };
+    let gesture_bindings = GestureBindings {
+        nod: gesture_binding("nod"),
+        shake: gesture_binding("shake"),
+        tilt: gesture_binding("tilt"),
+    };
+    // sweep your head to its comfortable limits for this many seconds at startup
+    let range_of_motion_calibration_s = 0.0;
     let polymouse_params = PolyMouseParams {
-        min_jump: 100.0,
-        speed_expand_factor: 0.0, // TODO translate delta->speed
+        min_jump: 2.5, // degrees of visual angle
+        jump_expansion: Some(PiecewiseLinear {
+            // (degrees per second of head rotation, degrees of visual angle)
+            points: vec![(20.0, 0.0), (150.0, 2.0)],
+        }),
         head_smoothing_factor: 0.05,
-        throw_thresh_speed: 200.0, // pixels per second
-        throw_speed: 8000.0, // pixels per second
+        throw_thresh_speed: 50.0, // mm per second
+        throw_speed: 2000.0, // mm per second
+        throw_style: choices.get("throw_style", ThrowStyle::parse, ThrowStyle::Linear),
+        head_landing_blend: 0.0,
         small_jump_factor: 0.75,
+        head_kalman: None, // e.g. Some(KalmanParams { process_noise: 1.0e5, measurement_noise: 0.05 })
+        magnet: Some(MagnetParams {
+            capture_radius: 1.5, // degrees of visual angle
+            sticky_gain: 0.5,
+            pull: 0.3,
+        }),
+    };
+    let head_speed_smoothing = 0.1; // seconds
+    let drift_params = DriftParams {
+        recenter_time: Some(600.0), // seconds
+        still_speed: 2.0, // degrees per second
+        reset_on_throw: false, // true stops recenter_time removing drift
+    };
+    // targets for the magnet come from this file if it exists, otherwise
+    // other programs can send them over this socket in $XDG_RUNTIME_DIR
+    let targets_file = "targets.txt";
+    let targets_socket = None; // e.g. Some("fusion_mouse_targets.sock")
+    let eye_movement_params = EyeMovementParams {
+        saccade_velocity: 50.0, // degrees of visual angle per second
+        max_dispersion: 1.5, // degrees of visual angle
+        dispersion_window: 0.1, // seconds
+        max_gap: 0.05, // seconds
+    };
+    let use_jump_adaptation = true;
+    let jump_adapt_params = JumpAdaptParams {
+        columns: 3,
+        rows: 3,
+        radius_per_error: 1.5,
+        min_jump: 1.5, // degrees of visual angle
+        max_jump: 6.0, // degrees of visual angle
+        small_jump_per_error: 1.0,
+        min_small_jump_factor: 0.5,
+        max_small_jump_factor: 1.0,
+        settle_time: 0.3, // seconds
+        learning_rate: 0.2,
+    };
+    // switch at runtime with GestureAction::NextPointingMode
+    let pointing_mode = PointingMode::PolyMouse;
+    // look to turn, best with `output enigo_relative` in choices.txt
+    let joystick_params = JoystickParams {
+        dead_zone: 5.0, // degrees of visual angle
+        rate: RateCurve {
+            gain: Acceleration {
+                cd_min: 10.0, // mm per second per degree
+                cd_max: 40.0, // mm per second per degree
+                v_min: 0.0, // degrees past the dead zone
+                v_max: 15.0, // degrees past the dead zone
+                lambda: 0.5,
+                ratio: 0.5,
+            },
+            max_offset: 15.0, // degrees past the dead zone
+        },
+        head_weight: 1.0,
+    };
+    let magic_params = MagicParams {
+        warp_threshold: 3.0, // degrees of visual angle
+        onset_speed: 20.0, // mm per second
     };
     let use_fixation_filter = false;
+    let use_gaze_kalman = false;
+    let gaze_kalman_params = KalmanParams {
+        process_noise: 600.0, // degrees²/s³
+        measurement_noise: 0.25, // degrees², 0.5° of tracker noise
+    };
+    // filter and pointer state is saved here periodically and on shutdown,
+    // and restored on startup
+    let snapshot_file = Path::new("fusion_mouse.snapshot");
+    let snapshot_interval = 60; // seconds
+    // e.g. `output uinput absolute` in choices.txt under Wayland, which needs
+    // write access to /dev/uinput, or `output enigo_relative` for games and
+    // remote desktops that capture the pointer
+    let output_backend = choices.get("output", OutputBackend::parse, OutputBackend::Enigo);
 
     // input state
     let mut raw_head_pose: Vector2<f32> = vec2(0.0, 0.0);
+    let mut raw_head_roll: f32 = 0.0;
     let mut raw_gaze: Vector2<f32> = vec2(0.0, 0.0);
 
     // pipeline state
     let mut last_head_tick = Instant::now();
-    let mut last_gaze_tick = Instant::now();
-    let mut head_filter = VecOneEuroFilter::new(6.0, 1000.0, 1.0);
-    let mut last_head_pose: Option<Vector2<f32>> = None;
+    let mut gaze_time: f64 = 0.0; // seconds on the tracker's clock
+    let mut last_gaze_time: Option<f64> = None;
+    let mut last_eyes_tick = Instant::now();
+    let mut head_filter: OneEuroFilter<Vector2<f32>> = OneEuroFilter::new(6.0, 1000.0, 1.0);
+    let mut roll_filter: OneEuroFilter<f32> = OneEuroFilter::new(6.0, 1000.0, 1.0);
+    let mut drift = DriftCompensator::new(drift_params);
+    let mut head_speed_estimator = HeadSpeedEstimator::new(head_speed_smoothing);
+    let mut rom_calibration = if range_of_motion_calibration_s > 0.0 {
+        println!("Look at the centre of the screen then turn your head to its comfortable limits");
+        Some(RangeOfMotionCalibration::new(range_of_motion_calibration_s))
+    } else {
+        None
+    };
 
-    let mut poly_mouse = PolyMouseTransform::new(polymouse_params.clone());
-    let mut freezer = FreezeTransformer::new(0.3);
+    let mut target_provider = target_provider(targets_file, targets_socket);
+    let mut pointer = Pointer::new(pointing_mode,
+                                   PolyMouseTransform::new(polymouse_params.clone(), &screen),
+                                   MagicTransform::new(magic_params, &screen),
+                                   GazeJoystick::new(joystick_params,
+                                                     display_layout.tracker(),
+                                                     &screen));
+    let mut clicker = ClickStateMachine::new(click_params);
+    let mut zoom = ZoomLens::new(zoom_params, &screen);
+    let mut jump_adapter = JumpAdapter::new(jump_adapt_params, display_layout.tracker(), &screen);
+    let mut dweller = DwellClicker::new(dwell_params, &screen);
+    let mut blink_detector = BlinkDetector::new(blink_params);
+    let mut head_scroll = HeadScroll::new(scroll_accel, scroll_dead_zone);
+    let mut scroll_toggled = false;
+    let mut scroll_key_held = false;
+    let mut scroll_dwelled = false;
+    let mut gestures = GestureRecognizer::new(gesture_params);
+    let mut gesture_start_pt: Option<Vector2<i32>> = None;
+    let mut paused = false;
 
-    let mut fixation_filter = FixationFilter::new(0.03, 150.0);
+    let mut fixation_filter = FixationFilter::new(0.03, // seconds
+                                                  4.0, // degrees per second
+                                                  0.075, // seconds
+                                                  1.5, // degrees of visual angle
+                                                  &screen);
+    let mut gaze_kalman = KalmanFilter::new(gaze_kalman_params.scaled(screen.deg_to_px(1.0)));
+    let mut eye_classifier = EyeMovementClassifier::new(eye_movement_params, &screen);
+    let mut eye_events: Vec<EyeEvent> = Vec::new();
+    let mut last_fixation: Option<(Vector2<f32>, f32)> = None;
+    let mut fixation_start: Option<Vector2<f32>> = None;
     let mut gaze_pt: Vector2<f32> = vec2(0.0, 0.0);
     let mut px_gaze: Vector2<f32> = vec2(0.0, 0.0);
 
-    let mut enigo = Enigo::new();
+    let mut output = pointer_output(output_backend, &display_layout);
+
+    // the drift neutral pose isn't included, it'd start from wherever the
+    // head was when the last session ended
+    match load_snapshot(snapshot_file,
+                        &mut [("head_filter", &mut head_filter),
+                              ("roll_filter", &mut roll_filter),
+                              ("head_speed", &mut head_speed_estimator),
+                              ("poly_mouse", &mut pointer.poly_mouse),
+                              ("clicker", &mut clicker),
+                              ("fixation_filter", &mut fixation_filter),
+                              ("gaze_kalman", &mut gaze_kalman)]) {
+        Ok(()) => println!("Restored pipeline state from {}", snapshot_file.display()),
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => println!("Error loading {}: {}", snapshot_file.display(), e),
+    }
+
+    macro_rules! save_pipeline {
+        () => {
+            if let Err(e) = save_snapshot(snapshot_file,
+                                          &[("head_filter", &head_filter),
+                                            ("roll_filter", &roll_filter),
+                                            ("head_speed", &head_speed_estimator),
+                                            ("poly_mouse", &pointer.poly_mouse),
+                                            ("clicker", &clicker),
+                                            ("fixation_filter", &fixation_filter),
+                                            ("gaze_kalman", &gaze_kalman)]) {
+                println!("Error saving {}: {}", snapshot_file.display(), e);
+            }
+        }
+    }
+    let mut last_save = Instant::now();
 
     loop {
+        if last_save.elapsed().as_secs() >= snapshot_interval {
+            save_pipeline!();
+            last_save = Instant::now();
+        }
+
         // update input state =========================
         let mut tick_gaze = false;
         let mut tick_head = false;
+        let mut gaze_valid = true;
+        let mut eyes_valid: Option<(bool, bool)> = None;
         match rx.recv().unwrap() {
-            Input::LinuxTrackHead { yaw, pitch } => {
+            Input::LinuxTrackHead { yaw, pitch, roll } => {
                 raw_head_pose = vec2(yaw, pitch) * -1.0;
+                raw_head_roll = roll;
                 tick_head = true;
             }
             #[cfg(feature = "tobii")]
-            Input::TobiiGaze { x, y } => {
+            Input::TobiiGaze { x, y, timestamp_us } => {
                 raw_gaze = vec2(x, y);
+                gaze_time = timestamp_us as f64 * 1.0e-6;
                 tick_gaze = true;
             }
+            #[cfg(feature = "tobii")]
+            Input::TobiiGazeLost { timestamp_us } => {
+                gaze_time = timestamp_us as f64 * 1.0e-6;
+                tick_gaze = true;
+                gaze_valid = false;
+            }
+            #[cfg(feature = "tobii")]
+            Input::TobiiEyes { left, right } => {
+                eyes_valid = Some((left, right));
+            }
+            #[cfg(target_os = "linux")]
+            Input::ScrollKey { pressed } => {
+                scroll_key_held = pressed;
+            }
             Input::Shutdown => break,
         }
         let _signpost = signpost::AutoTrace::new(1, &[0, 0, 0, signpost::Color::Blue as usize]);
 
         let tick = Instant::now();
-        let (display_width, display_height) = Enigo::main_display_size();
 
         // compute pipeline results ===================
         if tick_head {
             let dt = calc_dt(tick, &mut last_head_tick);
             let smoothed_head = head_filter.filter(raw_head_pose, dt);
+            let smoothed_roll = roll_filter.filter(raw_head_roll, dt);
             // let smoothed_head = raw_head_pose;
 
-            let head_delta = match last_head_pose {
-                Some(last_pose) => smoothed_head - last_pose,
-                None => vec2(0.0, 0.0),
+            let head_speed = head_speed_estimator.update(raw_head_pose, dt);
+            let head_delta = drift.delta(smoothed_head, head_speed, dt);
+
+            let calibration_done = match rom_calibration {
+                Some(ref mut cal) => cal.update(smoothed_head, dt),
+                None => false,
             };
-            last_head_pose = Some(smoothed_head);
+            if calibration_done {
+                let tracker = display_layout.tracker();
+                let aspect = tracker.width as f32 / tracker.height as f32;
+                head_gain = rom_calibration.take().unwrap().head_gain(aspect);
+                println!("Calibrated head gain: {:?}", head_gain);
+            }
+            if rom_calibration.is_some() {
+                continue;
+            }
 
-            let head_cursor_move = vec2(accel.transform(head_delta.x, dt),
-                                        accel.transform(head_delta.y, dt));
+            let gesture = gestures.update(vec3(smoothed_head.x, smoothed_head.y, smoothed_roll), dt);
+            if gestures.at_rest() || gesture_start_pt.is_none() {
+                gesture_start_pt = Some(output.location());
+            }
+            if let Some(action) = gesture.and_then(|g| gesture_bindings.action_for(g)) {
+                // undo any cursor movement from before the gesture armed
+                let start_pt = gesture_start_pt.unwrap_or(output.location());
+                output.move_to(start_pt);
+                clicker.hold(start_pt, gestures.params.cooldown);
+                match action {
+                    GestureAction::Click(click) => {
+                        perform_click(&mut *output, &mut clicker, click, start_pt)
+                    }
+                    GestureAction::ToggleScroll => scroll_toggled = !scroll_toggled,
+                    GestureAction::NextPointingMode => {
+                        pointer.mode = pointer.mode.next();
+                        println!("Pointing mode: {:?}", pointer.mode);
+                    }
+                    GestureAction::ToggleZoom => {
+                        if zoom.active() {
+                            zoom.close();
+                        } else {
+                            zoom.open(gaze_pt, start_pt, pointer.poly_mouse.jump_radius());
+                        }
+                    }
+                    GestureAction::Pause => paused = !paused,
+                    GestureAction::Recenter => {
+                        let tracker = display_layout.tracker();
+                        let center = vec2(tracker.x + tracker.width / 2,
+                                          tracker.y + tracker.height / 2);
+                        output.move_to(center);
+                        clicker.hold(center, gestures.params.cooldown);
+                        // where the head was before nodding, not the end of the nod
+                        match gestures.rest_pose() {
+                            Some(pose) => drift.recenter_at(vec2(pose.x, pose.y)),
+                            None => drift.recenter(),
+                        }
+                    }
+                }
+                continue;
+            }
+            if gestures.reversed() && gesture_bindings.any() {
+                // once the head turns back it's probably a gesture, so keep
+                // the cursor where the first stroke started until it's either
+                // a gesture or times out. A single fast stroke moves normally
+                // so throws and fast scrolling aren't swallowed.
+                if let Some(start_pt) = gesture_start_pt {
+                    if start_pt != output.location() {
+                        output.move_to(start_pt);
+                    }
+                }
+                continue;
+            }
+            if paused {
+                continue;
+            }
 
-            let (mouse_x, mouse_y) = Enigo::mouse_location();
-            let mouse_pt = vec2(mouse_x, mouse_y);
-            let dest = poly_mouse.transform(gaze_pt, mouse_pt, head_cursor_move, dt);
-            let confined = vec2(max(0, min(display_width as i32, dest.x)),
-                                max(0, min(display_height as i32, dest.y)));
-            let mouse_down = Enigo::pressed_buttons() != 0;
-            let new_pos = freezer.transform(confined, mouse_down, dt);
+            if scroll_toggled || scroll_key_held || scroll_dwelled {
+                let scroll = head_scroll.transform(head_gain.apply(head_delta), dt);
+                output.scroll(scroll);
+                if scroll_dwelled && head_scroll.still_time() >= scroll_exit_time {
+                    scroll_dwelled = false;
+                }
+                continue;
+            }
+
+            let accel_move = vec2(head_transfer_x.transform(head_delta.x, dt),
+                                  head_transfer_y.transform(head_delta.y, dt));
+            let head_cursor_move = head_gain.apply(accel_move) * screen.px_per_mm;
+
+            let mouse_pt = output.location();
+            let gaze_region = display_layout.region_at(vec2(gaze_pt.x as i32, gaze_pt.y as i32))
+                .map(|region| &region.behaviour);
+            let mouse_region = display_layout.region_at(mouse_pt);
+            let head_cursor_move = match mouse_region.and_then(|r| r.behaviour.sticky_gain) {
+                Some(gain) => head_cursor_move * gain,
+                None => head_cursor_move,
+            };
+            let targets = match target_provider {
+                Some(ref mut provider) => provider.targets(),
+                None => &[],
+            };
+            let buttons = output.pressed_buttons();
+            if zoom.active() && (buttons != 0 || !zoom.covers(gaze_pt)) {
+                // clicking confirms the position, looking away cancels
+                zoom.close();
+            }
+            let adapted_jump = if use_jump_adaptation {
+                jump_adapter.jump_size(gaze_pt)
+            } else {
+                None
+            };
+            let (mut min_jump, small_jump_factor) = adapted_jump
+                .unwrap_or((screen.deg_to_px(polymouse_params.min_jump),
+                            polymouse_params.small_jump_factor));
+            if let Some(region_jump) = gaze_region.and_then(|b| b.min_jump) {
+                min_jump = screen.deg_to_px(region_jump);
+            }
+            pointer.poly_mouse.set_jump_size(min_jump, small_jump_factor);
+            pointer.poly_mouse.set_throws_enabled(!gaze_region.map_or(false, |b| b.no_throw));
+            let was_throwing = pointer.poly_mouse.throwing();
+            let dest = if zoom.active() {
+                zoom.transform(head_cursor_move)
+            } else {
+                pointer.transform(&PointingInput {
+                                      gaze_pt,
+                                      fixation_start: fixation_start.take(),
+                                      fixation: eye_classifier.current_fixation(),
+                                      mouse_pt,
+                                      head_delta: head_cursor_move,
+                                      head_speed,
+                                      targets,
+                                      dt,
+                                  })
+            };
+            if zoom.params.after_throw && was_throwing && !pointer.poly_mouse.throwing() {
+                // the target is where the user is looking, the landing point
+                // only has to be somewhere in the view
+                zoom.open(gaze_pt, dest, pointer.poly_mouse.jump_radius());
+            }
+            // joystick motion turns a camera and relative output goes to
+            // something re-centering the pointer, neither is a place on the desktop
+            let confined = if pointer.mode == PointingMode::GazeJoystick || output.relative() {
+                dest
+            } else {
+                let mut confined = display_layout.confine(dest);
+                if let Some(region) = mouse_region {
+                    if region.behaviour.confine && !was_throwing && !pointer.poly_mouse.throwing() {
+                        confined = region.clamp(confined);
+                    }
+                }
+                confined
+            };
+            let new_pos = clicker.transform(confined, mouse_pt, buttons, dt);
+
+            let is_throwing = pointer.poly_mouse.throwing();
+            if !was_throwing && is_throwing {
+                jump_adapter.throw_started();
+                if drift.params.reset_on_throw {
+                    drift.recenter();
+                }
+            } else if was_throwing && !is_throwing {
+                jump_adapter.throw_landed(pointer.poly_mouse.last_jump_destination, new_pos);
+            } else {
+                jump_adapter.update(new_pos, buttons != 0, dt);
+            }
 
             if new_pos != mouse_pt {
-                enigo.mouse_move_to(confined.x, confined.y);
+                output.move_to(new_pos);
+            }
+
+            if use_dwell_click {
+                match dweller.update(new_pos, dt) {
+                    Some((DwellAction::Click(click), click_pt)) => {
+                        perform_click(&mut *output, &mut clicker, click, click_pt)
+                    }
+                    Some((DwellAction::Scroll, _)) => {
+                        scroll_dwelled = true;
+                        head_scroll.reset_still_time();
+                    }
+                    None => {}
+                }
             }
 
             // debugging =====================
@@ -137,35 +667,96 @@ fn run_pipeline(rx: Receiver<Input>, debug: DebugSender) {
             {
                 let mut debug_frame = DebugFrame {
                     points: Vec::with_capacity(4),
-                    display_width: display_width as f32,
-                    display_height: display_height as f32,
+                    display_width: main_monitor.width as f32,
+                    display_height: main_monitor.height as f32,
+                    lens: None,
                 };
                 let circle = DebugPoint {
                     offset: [dest.x as f32, dest.y as f32],
                     color: [0.0, 1.0, 0.0],
-                    size: poly_mouse.jump_radius()*2.0,
+                    size: pointer.poly_mouse.jump_radius()*2.0,
                 };
                 debug_frame.points.push(circle);
                 let circle2 = DebugPoint {
-                    offset: poly_mouse.last_jump_destination.into(),
+                    offset: pointer.poly_mouse.last_jump_destination.into(),
                     color: [0.0, 1.0, 0.0],
-                    size: polymouse_params.min_jump*polymouse_params.small_jump_factor*2.0,
+                    size: pointer.poly_mouse.small_jump_radius()*2.0,
                 };
                 debug_frame.points.push(circle2);
                 debug_frame.add_point(gaze_pt, [1.0, 0.0, 0.0]);
                 debug_frame.add_point(px_gaze, [1.0, 0.0, 1.0]);
+                if let Some((centroid, duration)) = last_fixation {
+                    debug_frame.points.push(DebugPoint {
+                        offset: centroid.into(),
+                        color: [0.0, 0.0, 1.0],
+                        size: 20.0 + duration * 100.0,
+                    });
+                }
+                if let Some(fixation) = eye_classifier.current_fixation() {
+                    debug_frame.add_point(fixation, [0.0, 0.0, 1.0]);
+                }
+                if let Some((center, radius, cursor)) = zoom.view() {
+                    debug_frame.lens = Some(Lens {
+                        center: center.into(),
+                        radius,
+                        magnification: zoom.params.magnification,
+                    });
+                    debug_frame.add_point(cursor, [1.0, 1.0, 1.0]);
+                }
+                if let Some((anchor, progress)) = dweller.progress() {
+                    if use_dwell_click && progress > 0.0 {
+                        debug_frame.points.push(DebugPoint {
+                            offset: anchor.into(),
+                            color: [1.0, 1.0, 0.0],
+                            size: 40.0 * progress,
+                        });
+                    }
+                }
                 debug.send(debug_frame);
             }
             #[cfg(not(feature = "viz-2d"))]
-            let _silence_warnings = (&px_gaze, &debug);
+            let _silence_warnings = (&px_gaze, &debug, &last_fixation);
+        }
+
+        if let Some((left, right)) = eyes_valid {
+            let dt = calc_dt(tick, &mut last_eyes_tick);
+            let click = blink_detector
+                .update(left, right, dt)
+                .and_then(|event| blink_bindings.click_for(event))
+                .filter(|_| !paused);
+            if let Some(click) = click {
+                // head movement carries on while the eyes are closed, so
+                // click where the cursor is now
+                let mouse_pt = output.location();
+                perform_click(&mut *output, &mut clicker, click, mouse_pt);
+            }
+        }
+
+        if tick_gaze && !gaze_valid {
+            let dt = calc_tracker_dt(gaze_time, &mut last_gaze_time);
+            eye_events.clear();
+            eye_classifier.classify(None, dt, &mut eye_events);
         }
 
-        if tick_gaze {
-            let dt = calc_dt(tick, &mut last_gaze_tick);
-            px_gaze = vec2(raw_gaze.x * (display_width as f32),
-                           raw_gaze.y * (display_height as f32));
+        if tick_gaze && gaze_valid {
+            let dt = calc_tracker_dt(gaze_time, &mut last_gaze_time);
+            px_gaze = display_layout.gaze_to_global(raw_gaze);
+
+            eye_events.clear();
+            eye_classifier.classify(Some(px_gaze), dt, &mut eye_events);
+            for event in &eye_events {
+                match *event {
+                    EyeEvent::Start { kind: EyeMovement::Fixation, pt } => fixation_start = Some(pt),
+                    EyeEvent::End { kind: EyeMovement::Fixation, centroid, duration } => {
+                        last_fixation = Some((centroid, duration));
+                    }
+                    _ => (),
+                }
+            }
             if use_fixation_filter {
-                gaze_pt = fixation_filter.transform(px_gaze, dt);
+                gaze_pt = fixation_filter.transform(px_gaze, gaze_time);
+            } else if use_gaze_kalman {
+                gaze_pt = gaze_kalman.filter(px_gaze, dt);
             } else {
                 gaze_pt = px_gaze;
             }
@@ -173,12 +764,27 @@ fn run_pipeline(rx: Receiver<Input>, debug: DebugSender) {
             // println!("GAZE {:?}", gaze_pt);
         }
     }
+
+    save_pipeline!();
 }
 
 fn main() {
     println!("Hello, world!");
     let (mut pool, rx) = InputPool::new();
     pool.spawn(ltr_input::listen);
+    // scroll while this key is held, e.g. a foot pedal that shows up as a
+    // keyboard, grabbed so its presses don't reach other programs:
+    // Some(KeyParams { device: PathBuf::from("/dev/input/by-id/usb-pedal-event-kbd"),
+    //                  code: 183, // KEY_F13
+    //                  grab: true })
+    #[cfg(target_os = "linux")]
+    let scroll_key: Option<KeyParams> = None;
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(params) = scroll_key {
+            pool.spawn(move |tx, inbox| key_input::listen(params, tx, inbox));
+        }
+    }
     #[cfg(feature = "tobii")]
     pool.spawn(tobii_input::listen);
 
@@ -190,10 +796,10 @@ fn main() {
     let handle = thread::spawn(|| run_pipeline(rx, debug_sender));
 
     #[cfg(feature = "viz-2d")]
-    {
-        debug_view.run();
-        mem::drop(pool);
-    }
+    debug_view.run();
+    #[cfg(not(feature = "viz-2d"))]
+    interrupt::wait();
 
+    mem::drop(pool);
     handle.join().unwrap();
 }
@@ -0,0 +1,165 @@
+use cgmath::{vec2, Vector2};
+use enigo::{Enigo, MouseButton, MouseControllable};
+
+use transforms::{Button, ButtonAction};
+#[cfg(target_os = "linux")]
+use uinput_output::UinputMode;
+
+#[derive(Clone, Copy, Debug)]
+pub enum OutputBackend {
+    Enigo,
+    /// only relative motion, for games and remote desktops
+    EnigoRelative,
+    #[cfg(target_os = "linux")]
+    Uinput(UinputMode),
+}
+
+impl OutputBackend {
+    /// `enigo`, `enigo_relative`, or `uinput relative` or `uinput absolute`
+    /// on Linux
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut words = text.split_whitespace();
+        let backend = match words.next()? {
+            "enigo" => OutputBackend::Enigo,
+            "enigo_relative" => OutputBackend::EnigoRelative,
+            #[cfg(target_os = "linux")]
+            "uinput" => OutputBackend::Uinput(UinputMode::parse(words.next()?)?),
+            _ => return None,
+        };
+        if words.next().is_some() {
+            return None;
+        }
+        Some(backend)
+    }
+}
+
+/// Where cursor movement, clicks and scrolling end up
+pub trait PointerOutput {
+    fn move_to(&mut self, pt: Vector2<i32>);
+    fn button(&mut self, action: ButtonAction);
+    /// in lines, positive is right and down
+    fn scroll(&mut self, amount: Vector2<i32>);
+    /// where the cursor is now in global desktop pixels
+    fn location(&self) -> Vector2<i32>;
+    /// buttons held down, as a mask of `Button::mask` bits
+    fn pressed_buttons(&self) -> usize;
+    /// whether the motion is taken by something that re-centers the pointer,
+    /// so where it ends up isn't a place on the desktop to keep in bounds
+    fn relative(&self) -> bool {
+        false
+    }
+}
+
+/// Moves the system cursor directly, which needs X11 or macOS
+pub struct EnigoOutput {
+    enigo: Enigo,
+}
+
+impl EnigoOutput {
+    pub fn new() -> Self {
+        EnigoOutput { enigo: Enigo::new() }
+    }
+}
+
+fn enigo_button(button: Button) -> MouseButton {
+    match button {
+        Button::Left => MouseButton::Left,
+        Button::Right => MouseButton::Right,
+        Button::Middle => MouseButton::Middle,
+    }
+}
+
+impl PointerOutput for EnigoOutput {
+    fn move_to(&mut self, pt: Vector2<i32>) {
+        self.enigo.mouse_move_to(pt.x, pt.y);
+    }
+
+    fn button(&mut self, action: ButtonAction) {
+        match action {
+            ButtonAction::Down(button) => self.enigo.mouse_down(enigo_button(button)),
+            ButtonAction::Up(button) => self.enigo.mouse_up(enigo_button(button)),
+        }
+    }
+
+    fn scroll(&mut self, amount: Vector2<i32>) {
+        if amount.x != 0 {
+            self.enigo.mouse_scroll_x(amount.x);
+        }
+        if amount.y != 0 {
+            self.enigo.mouse_scroll_y(amount.y);
+        }
+    }
+
+    fn location(&self) -> Vector2<i32> {
+        let (x, y) = Enigo::mouse_location();
+        vec2(x, y)
+    }
+
+    fn pressed_buttons(&self) -> usize {
+        Enigo::pressed_buttons()
+    }
+}
+
+/// Sends only relative motion and never asks the OS where the cursor is, for
+/// games and remote desktops that capture the pointer and keep re-centering
+/// it. The cursor position is wherever the motion sent so far has taken it.
+/// Buttons are still read from the OS, they aren't affected by the capture.
+pub struct RelativeOutput {
+    enigo: Enigo,
+    pos: Vector2<i32>,
+    buttons: usize,
+}
+
+impl RelativeOutput {
+    pub fn new(start: Vector2<i32>) -> Self {
+        RelativeOutput {
+            enigo: Enigo::new(),
+            pos: start,
+            buttons: 0,
+        }
+    }
+}
+
+impl PointerOutput for RelativeOutput {
+    fn move_to(&mut self, pt: Vector2<i32>) {
+        let delta = pt - self.pos;
+        if delta.x != 0 || delta.y != 0 {
+            self.enigo.mouse_move_relative(delta.x, delta.y);
+        }
+        self.pos = pt;
+    }
+
+    fn button(&mut self, action: ButtonAction) {
+        match action {
+            ButtonAction::Down(button) => {
+                self.buttons |= button.mask();
+                self.enigo.mouse_down(enigo_button(button));
+            }
+            ButtonAction::Up(button) => {
+                self.buttons &= !button.mask();
+                self.enigo.mouse_up(enigo_button(button));
+            }
+        }
+    }
+
+    fn scroll(&mut self, amount: Vector2<i32>) {
+        if amount.x != 0 {
+            self.enigo.mouse_scroll_x(amount.x);
+        }
+        if amount.y != 0 {
+            self.enigo.mouse_scroll_y(amount.y);
+        }
+    }
+
+    fn location(&self) -> Vector2<i32> {
+        self.pos
+    }
+
+    fn pressed_buttons(&self) -> usize {
+        Enigo::pressed_buttons() | self.buttons
+    }
+
+    fn relative(&self) -> bool {
+        true
+    }
+}
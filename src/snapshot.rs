@@ -0,0 +1,250 @@
+use std::collections::VecDeque;
+use std::fmt::Display;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use cgmath::{vec2, vec3, Vector2, Vector3};
+
+/// Pipeline state that can be saved and later restored, so a session can be
+/// resumed without the filters seeding themselves on the first sample again.
+pub trait Snapshot {
+    fn save(&self, out: &mut SnapshotWriter);
+    fn restore(&mut self, input: &mut SnapshotReader) -> Result<(), String>;
+}
+
+/// A value with a fixed number of components, for state that's generic over
+/// the kind of sample it holds
+pub trait SnapshotValue: Sized {
+    fn write_to(&self, out: &mut SnapshotWriter);
+    fn read_from(input: &mut SnapshotReader) -> Result<Self, String>;
+}
+
+impl SnapshotValue for f32 {
+    fn write_to(&self, out: &mut SnapshotWriter) {
+        out.put(self);
+    }
+
+    fn read_from(input: &mut SnapshotReader) -> Result<Self, String> {
+        input.get()
+    }
+}
+
+impl SnapshotValue for f64 {
+    fn write_to(&self, out: &mut SnapshotWriter) {
+        out.put(self);
+    }
+
+    fn read_from(input: &mut SnapshotReader) -> Result<Self, String> {
+        input.get()
+    }
+}
+
+impl<S: SnapshotValue> SnapshotValue for Vector2<S> {
+    fn write_to(&self, out: &mut SnapshotWriter) {
+        self.x.write_to(out);
+        self.y.write_to(out);
+    }
+
+    fn read_from(input: &mut SnapshotReader) -> Result<Self, String> {
+        Ok(vec2(S::read_from(input)?, S::read_from(input)?))
+    }
+}
+
+impl<S: SnapshotValue> SnapshotValue for Vector3<S> {
+    fn write_to(&self, out: &mut SnapshotWriter) {
+        self.x.write_to(out);
+        self.y.write_to(out);
+        self.z.write_to(out);
+    }
+
+    fn read_from(input: &mut SnapshotReader) -> Result<Self, String> {
+        Ok(vec3(S::read_from(input)?, S::read_from(input)?, S::read_from(input)?))
+    }
+}
+
+/// Collects the values of one part of the pipeline as whitespace separated text
+pub struct SnapshotWriter {
+    values: Vec<String>,
+}
+
+impl SnapshotWriter {
+    pub fn put<T: Display>(&mut self, value: T) {
+        self.values.push(value.to_string());
+    }
+
+    pub fn put_vec2(&mut self, v: Vector2<f32>) {
+        self.put(v.x);
+        self.put(v.y);
+    }
+
+    pub fn put_option<T, F: FnOnce(&mut Self, &T)>(&mut self, value: &Option<T>, put: F) {
+        self.put(value.is_some());
+        if let Some(ref value) = *value {
+            put(self, value);
+        }
+    }
+}
+
+pub struct SnapshotReader<'a> {
+    values: VecDeque<&'a str>,
+}
+
+impl<'a> SnapshotReader<'a> {
+    pub fn get<T: FromStr>(&mut self) -> Result<T, String> {
+        let text = self.values.pop_front().ok_or("snapshot ended early")?;
+        text.parse().map_err(|_| format!("bad value in snapshot: {}", text))
+    }
+
+    pub fn get_vec2(&mut self) -> Result<Vector2<f32>, String> {
+        Ok(vec2(self.get()?, self.get()?))
+    }
+
+    pub fn get_option<T, F: FnOnce(&mut Self) -> Result<T, String>>(&mut self,
+                                                                       get: F)
+                                                                       -> Result<Option<T>, String> {
+        if self.get()? { Ok(Some(get(self)?)) } else { Ok(None) }
+    }
+}
+
+/// Bump this whenever a part's format changes, older files are then ignored
+/// instead of being read wrongly
+const SNAPSHOT_VERSION: u32 = 3;
+
+/// Writes a version line then each part on its own line starting with its name.
+/// The file is written next to `path` and renamed over it, so a crash part
+/// way through leaves the previous snapshot rather than half of this one.
+pub fn save_snapshot(path: &Path, parts: &[(&str, &dyn Snapshot)]) -> io::Result<()> {
+    let mut text = format!("version {}\n", SNAPSHOT_VERSION);
+    for &(name, part) in parts {
+        let mut out = SnapshotWriter { values: vec![name.to_owned()] };
+        part.save(&mut out);
+        text.push_str(&out.values.join(" "));
+        text.push('\n');
+    }
+    let mut temp_name = path.as_os_str().to_owned();
+    temp_name.push(".tmp");
+    let temp_path = PathBuf::from(temp_name);
+    {
+        let mut file = File::create(&temp_path)?;
+        file.write_all(text.as_bytes())?;
+        file.sync_all()?;
+    }
+    fs::rename(&temp_path, path)
+}
+
+/// Restores the parts saved by `save_snapshot`, in the same order. Either
+/// every part is restored or, if anything in the file is wrong, none are.
+pub fn load_snapshot(path: &Path, parts: &mut [(&str, &mut dyn Snapshot)]) -> io::Result<()> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    let backups: Vec<Vec<String>> = parts.iter()
+        .map(|&(_, ref part)| {
+                 let mut out = SnapshotWriter { values: Vec::new() };
+                 part.save(&mut out);
+                 out.values
+             })
+        .collect();
+    let result = restore_parts(&text, parts);
+    if result.is_err() {
+        for (&mut (name, ref mut part), backup) in parts.iter_mut().zip(&backups) {
+            let mut input = SnapshotReader { values: backup.iter().map(|s| s.as_str()).collect() };
+            part.restore(&mut input)
+                .unwrap_or_else(|e| panic!("can't put back {} after a bad snapshot: {}", name, e));
+        }
+    }
+    result
+}
+
+fn restore_parts(text: &str, parts: &mut [(&str, &mut dyn Snapshot)]) -> io::Result<()> {
+    let mut lines = text.lines();
+    let header = lines.next().unwrap_or("");
+    if header != format!("version {}", SNAPSHOT_VERSION) {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                  format!("expected snapshot version {}, got: {}",
+                                          SNAPSHOT_VERSION,
+                                          header)));
+    }
+    for &mut (name, ref mut part) in parts.iter_mut() {
+        let invalid = |e: String| io::Error::new(io::ErrorKind::InvalidData, e);
+        let line = lines.next().ok_or_else(|| invalid(format!("{} missing from snapshot", name)))?;
+        let mut values: VecDeque<&str> = line.split_whitespace().collect();
+        if values.pop_front() != Some(name) {
+            return Err(invalid(format!("expected {} in snapshot, got: {}", name, line)));
+        }
+        let mut input = SnapshotReader { values };
+        part.restore(&mut input).map_err(&invalid)?;
+        if !input.values.is_empty() {
+            return Err(invalid(format!("extra values for {} in snapshot", name)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    #[derive(Debug, PartialEq)]
+    struct Part {
+        count: u32,
+        pos: Vector2<f32>,
+    }
+
+    impl Snapshot for Part {
+        fn save(&self, out: &mut SnapshotWriter) {
+            out.put(self.count);
+            out.put_vec2(self.pos);
+        }
+
+        fn restore(&mut self, input: &mut SnapshotReader) -> Result<(), String> {
+            self.count = input.get()?;
+            self.pos = input.get_vec2()?;
+            Ok(())
+        }
+    }
+
+    fn part(count: u32, x: f32) -> Part {
+        Part {
+            count,
+            pos: vec2(x, -x),
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("fusion_mouse_{}_{}.snapshot", name, process::id()))
+    }
+
+    #[test]
+    fn saved_parts_load_back() {
+        let path = temp_path("round_trip");
+        save_snapshot(&path, &[("a", &part(1, 0.5)), ("b", &part(2, -1.25))]).unwrap();
+        let (mut a, mut b) = (part(0, 0.0), part(0, 0.0));
+        load_snapshot(&path, &mut [("a", &mut a), ("b", &mut b)]).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(a, part(1, 0.5));
+        assert_eq!(b, part(2, -1.25));
+    }
+
+    #[test]
+    fn bad_snapshot_restores_nothing() {
+        let path = temp_path("rollback");
+        save_snapshot(&path, &[("a", &part(1, 0.5)), ("b", &part(2, -1.25))]).unwrap();
+        let mut text = String::new();
+        File::open(&path).unwrap().read_to_string(&mut text).unwrap();
+        // the first part is fine, the second has a value that won't parse
+        let text = text.replace("b 2", "b x");
+        File::create(&path).unwrap().write_all(text.as_bytes()).unwrap();
+
+        let (mut a, mut b) = (part(7, 3.0), part(8, 4.0));
+        assert!(load_snapshot(&path, &mut [("a", &mut a), ("b", &mut b)]).is_err());
+        // a different part list doesn't match either
+        assert!(load_snapshot(&path, &mut [("b", &mut b), ("a", &mut a)]).is_err());
+        fs::remove_file(&path).unwrap();
+        assert_eq!(a, part(7, 3.0));
+        assert_eq!(b, part(8, 4.0));
+    }
+}
@@ -0,0 +1,240 @@
+use std::env;
+use std::fs::{self, File, Permissions};
+use std::io::{self, BufRead, BufReader, Read};
+use std::mem;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::UnixListener;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use cgmath::{vec2, Vector2, InnerSpace, MetricSpace};
+
+use display::ScreenGeometry;
+
+/// A clickable rectangle in global desktop pixels
+#[derive(Clone, Debug)]
+pub struct Target {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Target {
+    pub fn center(&self) -> Vector2<f32> {
+        vec2(self.x + self.width / 2.0, self.y + self.height / 2.0)
+    }
+
+    pub fn contains(&self, pt: Vector2<f32>) -> bool {
+        pt.x >= self.x && pt.x < self.x + self.width && pt.y >= self.y &&
+        pt.y < self.y + self.height
+    }
+
+    /// Distance from `pt` to the nearest edge, zero inside
+    pub fn distance(&self, pt: Vector2<f32>) -> f32 {
+        let nearest = vec2(pt.x.max(self.x).min(self.x + self.width),
+                           pt.y.max(self.y).min(self.y + self.height));
+        nearest.distance(pt)
+    }
+
+    /// Parses one `x y width height` rectangle, `None` for anything else
+    pub fn parse(line: &str) -> Option<Target> {
+        let nums: Vec<f32> = line.split_whitespace()
+            .map(|s| s.parse::<f32>())
+            .collect::<Result<_, _>>()
+            .ok()?;
+        if nums.len() != 4 {
+            return None;
+        }
+        Some(Target {
+                 x: nums[0],
+                 y: nums[1],
+                 width: nums[2],
+                 height: nums[3],
+             })
+    }
+}
+
+/// Something that knows where the clickable things on screen are
+pub trait TargetProvider {
+    fn targets(&mut self) -> &[Target];
+}
+
+/// A fixed list of targets, one `x y width height` per line in a file
+pub struct StaticTargets {
+    targets: Vec<Target>,
+}
+
+impl StaticTargets {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+        let mut targets = Vec::new();
+        for line in text.lines() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            match Target::parse(line) {
+                Some(target) => targets.push(target),
+                None => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData,
+                                              format!("bad target: {}", line)))
+                }
+            }
+        }
+        Ok(StaticTargets { targets })
+    }
+}
+
+impl TargetProvider for StaticTargets {
+    fn targets(&mut self) -> &[Target] {
+        &self.targets
+    }
+}
+
+/// Where a socket named `name` goes, `None` if there's no `$XDG_RUNTIME_DIR`
+/// since anywhere else could be shared with other users
+pub fn runtime_socket_path(name: &str) -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR").map(|dir| PathBuf::from(dir).join(name))
+}
+
+/// Targets sent by another program over a unix socket. Clients write one
+/// `x y width height` per line and a blank line to replace the current set.
+/// Only our own user can connect to it.
+pub struct SocketTargets {
+    shared: Arc<Mutex<Vec<Target>>>,
+    targets: Vec<Target>,
+    path: PathBuf,
+}
+
+impl SocketTargets {
+    pub fn listen(path: &Path) -> io::Result<Self> {
+        // a socket left over from a previous run can go, anything else isn't ours
+        match fs::symlink_metadata(path) {
+            Ok(ref meta) if meta.file_type().is_socket() => fs::remove_file(path)?,
+            Ok(_) => {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists,
+                                          format!("{} exists and isn't a socket",
+                                                  path.display())))
+            }
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        let listener = UnixListener::bind(path)?;
+        fs::set_permissions(path, Permissions::from_mode(0o600))?;
+        let shared = Arc::new(Mutex::new(Vec::new()));
+        let thread_shared = shared.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+                let mut pending = Vec::new();
+                for line in BufReader::new(stream).lines() {
+                    let line = match line {
+                        Ok(line) => line,
+                        Err(_) => break,
+                    };
+                    if line.trim().is_empty() {
+                        *thread_shared.lock().unwrap() = mem::replace(&mut pending, Vec::new());
+                    } else if let Some(target) = Target::parse(&line) {
+                        pending.push(target);
+                    }
+                }
+            }
+        });
+        Ok(SocketTargets {
+               shared,
+               targets: Vec::new(),
+               path: path.to_owned(),
+           })
+    }
+}
+
+impl TargetProvider for SocketTargets {
+    fn targets(&mut self) -> &[Target] {
+        self.targets.clone_from(&self.shared.lock().unwrap());
+        &self.targets
+    }
+}
+
+impl Drop for SocketTargets {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+#[derive(Clone)]
+pub struct MagnetParams {
+    /// degrees of visual angle around the gaze point to look for targets
+    pub capture_radius: f32,
+    /// gain of fine head movement while the cursor is over a target
+    pub sticky_gain: f32,
+    /// from 0 to 1, how much fine head movement heading towards the nearest
+    /// target in the capture radius is turned to point straight at it
+    pub pull: f32,
+}
+
+/// Pulls throws onto the nearest target like a bubble cursor, and makes
+/// targets sticky so fine head movement doesn't slip off them.
+pub struct TargetMagnet {
+    capture_radius: f32,
+    sticky_gain: f32,
+    pull: f32,
+}
+
+impl TargetMagnet {
+    pub fn new(params: MagnetParams, screen: &ScreenGeometry) -> Self {
+        TargetMagnet {
+            capture_radius: screen.deg_to_px(params.capture_radius),
+            sticky_gain: params.sticky_gain,
+            pull: params.pull,
+        }
+    }
+
+    /// Centre of the target nearest `pt` if one is within the capture radius
+    pub fn snap(&self, pt: Vector2<f32>, targets: &[Target]) -> Option<Vector2<f32>> {
+        self.nearest(pt, targets).map(|target| target.center())
+    }
+
+    fn nearest<'a>(&self, pt: Vector2<f32>, targets: &'a [Target]) -> Option<&'a Target> {
+        let mut best: Option<(f32, &Target)> = None;
+        for target in targets {
+            let dist = target.distance(pt);
+            if dist <= self.capture_radius && best.map_or(true, |(d, _)| dist < d) {
+                best = Some((dist, target));
+            }
+        }
+        best.map(|(_, target)| target)
+    }
+
+    /// Sticky over a target, and bent towards the nearest one when heading
+    /// its way. The length of the movement is kept so it doesn't speed up.
+
+    pub fn fine_move(&self,
+                     mouse_pt: Vector2<f32>,
+                     delta: Vector2<f32>,
+                     targets: &[Target])
+                     -> Vector2<f32> {
+        if targets.iter().any(|target| target.contains(mouse_pt)) {
+            return delta * self.sticky_gain;
+        }
+        let length = delta.magnitude();
+        let to_target = match self.nearest(mouse_pt, targets) {
+            Some(target) => target.center() - mouse_pt,
+            None => return delta,
+        };
+        if length <= 0.0 || to_target.magnitude() <= 0.0 || delta.dot(to_target) <= 0.0 {
+            return delta;
+        }
+        let dir = delta / length;
+        let bent = dir + (to_target.normalize() - dir) * self.pull;
+        if bent.magnitude() <= 0.0 {
+            return delta;
+        }
+        bent.normalize() * length
+    }
+}
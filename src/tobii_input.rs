@@ -30,18 +30,32 @@ unsafe extern "C" fn gaze_callback(gaze_point: *const GazePoint,
     assert_ne!(user_data, ptr::null_mut());
     let context = &*(user_data as *mut CallbackContext);
     let pt = &*gaze_point;
-    if pt.validity != TOBII_VALIDITY_VALID {
+    let event = if pt.validity != TOBII_VALIDITY_VALID {
         // println!("INVALID {}", pt.timestamp_us);
-        return;
-    }
-    let event = Input::TobiiGaze {
-        x: pt.position_xy[0],
-        y: pt.position_xy[1],
+        Input::TobiiGazeLost { timestamp_us: pt.timestamp_us }
+    } else {
+        Input::TobiiGaze {
+            x: pt.position_xy[0],
+            y: pt.position_xy[1],
+            timestamp_us: pt.timestamp_us,
+        }
     };
     signpost::trace(2, &[0, 0, 0, signpost::Color::Red as usize]);
     context.output.send(event).unwrap();
 }
 
+unsafe extern "C" fn gaze_origin_callback(gaze_origin: *const GazeOrigin,
+                                          user_data: *mut ::std::os::raw::c_void) {
+    assert_ne!(user_data, ptr::null_mut());
+    let context = &*(user_data as *mut CallbackContext);
+    let origin = &*gaze_origin;
+    let event = Input::TobiiEyes {
+        left: origin.left_validity == TOBII_VALIDITY_VALID,
+        right: origin.right_validity == TOBII_VALIDITY_VALID,
+    };
+    context.output.send(event).unwrap();
+}
+
 unsafe fn input_loop(output: SyncSender<Input>,
                      inbox: Receiver<InputAction>)
                      -> Result<(), TobiiError> {
@@ -76,14 +90,16 @@ unsafe fn input_loop(output: SyncSender<Input>,
     let device = PtrWrapper::new(device_ptr, tobii_device_destroy);
 
     let mut context = Box::new(CallbackContext { output });
-    let context_borrow = context.as_mut();
-    let status = tobii_gaze_point_subscribe(device.ptr(),
-                                            Some(gaze_callback),
-                                            (context_borrow as *mut CallbackContext) as
-                                            *mut raw::c_void);
+    let context_ptr = (context.as_mut() as *mut CallbackContext) as *mut raw::c_void;
+    let status = tobii_gaze_point_subscribe(device.ptr(), Some(gaze_callback), context_ptr);
     let _subscription = PtrWrapper::new(device.ptr(), tobii_gaze_point_unsubscribe);
     status_to_result(status)?;
 
+    // per eye validity for blink and wink detection
+    let status = tobii_gaze_origin_subscribe(device.ptr(), Some(gaze_origin_callback), context_ptr);
+    let _origin_subscription = PtrWrapper::new(device.ptr(), tobii_gaze_origin_unsubscribe);
+    status_to_result(status)?;
+
     loop {
         match inbox.try_recv() {
             Ok(InputAction::Shutdown) => break,
@@ -0,0 +1,346 @@
+use std::f32;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Maps a movement to a gain depending on how fast it was made.
+///
+/// Velocities are in input units per second and gains are output units per
+/// input unit, for head pointing that's degrees/s and mm on screen per degree.
+pub trait TransferFunction {
+    fn gain(&self, speed: f32) -> f32;
+
+    fn transform(&self, diff: f32, dt: f32) -> f32 {
+        if dt <= 0.0 {
+            return 0.0;
+        }
+        diff * self.gain((diff / dt).abs())
+    }
+}
+
+/// Based on page 16 of Mathieu Nancel's "Mid-Air Pointing on Ultra-Walls" paper
+/// See the paper for how to set the constants.
+#[derive(Clone)]
+pub struct Acceleration {
+    pub cd_min: f32,
+    pub cd_max: f32,
+    pub v_min: f32,
+    pub v_max: f32,
+    pub lambda: f32,
+    pub ratio: f32,
+}
+
+impl TransferFunction for Acceleration {
+    fn gain(&self, speed: f32) -> f32 {
+        let v_inf = self.ratio * (self.v_max - self.v_min) + self.v_min;
+        let exponent = -self.lambda * (speed - v_inf);
+        ((self.cd_max - self.cd_min) / (1.0 + f32::exp(exponent))) + self.cd_min
+    }
+}
+
+#[derive(Clone)]
+pub struct ConstantGain {
+    pub gain: f32,
+}
+
+impl TransferFunction for ConstantGain {
+    fn gain(&self, _speed: f32) -> f32 {
+        self.gain
+    }
+}
+
+/// gain = scale * (speed / ref_speed)^exponent, clamped to `max_gain`
+#[derive(Clone)]
+pub struct PowerLaw {
+    pub scale: f32,
+    pub ref_speed: f32,
+    pub exponent: f32,
+    pub max_gain: f32,
+}
+
+impl TransferFunction for PowerLaw {
+    fn gain(&self, speed: f32) -> f32 {
+        (self.scale * (speed / self.ref_speed).powf(self.exponent)).min(self.max_gain)
+    }
+}
+
+/// Linear interpolation between (speed, gain) points, held constant past the ends
+#[derive(Clone, Debug)]
+pub struct PiecewiseLinear {
+    pub points: Vec<(f32, f32)>,
+}
+
+impl PiecewiseLinear {
+    /// Parses one `speed gain` pair per line, blank lines and `#` comments are skipped
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut points = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let nums: Vec<f32> = line.split_whitespace()
+                .map(|s| s.parse::<f32>())
+                .collect::<Result<_, _>>()
+                .map_err(|e| format!("line {}: {}", i + 1, e))?;
+            if nums.len() != 2 {
+                return Err(format!("line {}: expected speed and gain", i + 1));
+            }
+            if nums.iter().any(|n| !n.is_finite()) {
+                return Err(format!("line {}: speed and gain have to be finite", i + 1));
+            }
+            points.push((nums[0], nums[1]));
+        }
+        if points.is_empty() {
+            return Err("no points in curve".to_string());
+        }
+        points.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        Ok(PiecewiseLinear { points })
+    }
+
+    pub fn interpolate(&self, x: f32) -> f32 {
+        let first = self.points[0];
+        if x <= first.0 {
+            return first.1;
+        }
+        for pair in self.points.windows(2) {
+            let (x0, y0) = pair[0];
+            let (x1, y1) = pair[1];
+            if x <= x1 {
+                return y0 + (y1 - y0) * (x - x0) / (x1 - x0);
+            }
+        }
+        self.points[self.points.len() - 1].1
+    }
+}
+
+impl TransferFunction for PiecewiseLinear {
+    fn gain(&self, speed: f32) -> f32 {
+        self.interpolate(speed)
+    }
+}
+
+/// The shape of Windows' "enhance pointer precision" curve, which maps input
+/// speed to output speed through the default SmoothMouseXCurve/YCurve points.
+/// `input_scale` converts our speeds into the curve's units and `output_scale`
+/// converts the curve's output speed back into ours.
+#[derive(Clone)]
+pub struct WindowsCurve {
+    pub input_scale: f32,
+    pub output_scale: f32,
+    curve: PiecewiseLinear,
+}
+
+impl WindowsCurve {
+    pub fn new(input_scale: f32, output_scale: f32) -> Self {
+        WindowsCurve {
+            input_scale,
+            output_scale,
+            curve: PiecewiseLinear {
+                points: vec![(0.0, 0.0), (0.43, 1.37), (1.25, 5.30), (3.86, 24.30), (40.0, 568.0)],
+            },
+        }
+    }
+}
+
+impl TransferFunction for WindowsCurve {
+    fn gain(&self, speed: f32) -> f32 {
+        let x = speed * self.input_scale;
+        let (x0, y0) = self.curve.points[1];
+        if x <= x0 {
+            // the curve is linear through the origin at first
+            return y0 / x0 * self.output_scale * self.input_scale;
+        }
+        let last = self.curve.points[self.curve.points.len() - 1];
+        let y = if x > last.0 {
+            // extrapolate the last segment like Windows does
+            let prev = self.curve.points[self.curve.points.len() - 2];
+            last.1 + (last.1 - prev.1) * (x - last.0) / (last.0 - prev.0)
+        } else {
+            self.curve.interpolate(x)
+        };
+        (y * self.output_scale) / speed
+    }
+}
+
+/// Like libinput's adaptive profile: flat gain up to `threshold` then rising
+/// linearly with `incline` until it reaches `max_accel` times `base_gain`
+#[derive(Clone)]
+pub struct AdaptiveProfile {
+    pub base_gain: f32,
+    pub threshold: f32,
+    pub incline: f32,
+    pub max_accel: f32,
+}
+
+impl TransferFunction for AdaptiveProfile {
+    fn gain(&self, speed: f32) -> f32 {
+        let factor = 1.0 + self.incline * (speed - self.threshold).max(0.0);
+        self.base_gain * factor.min(self.max_accel)
+    }
+}
+
+/// Speed for how far a joystick is pushed rather than a gain for how fast
+/// something moved. Takes degrees past the dead zone and gives mm/s, using
+/// the offset in place of speed for an `Acceleration` curve so the gain in mm/s
+/// per degree rises from `cd_min` for small offsets to `cd_max` for large ones.
+#[derive(Clone)]
+pub struct RateCurve {
+    pub gain: Acceleration,
+    /// degrees, further out moves at the same rate as here
+    pub max_offset: f32,
+}
+
+impl RateCurve {
+    pub fn rate(&self, offset: f32) -> f32 {
+        if offset <= 0.0 {
+            return 0.0;
+        }
+        let offset = offset.min(self.max_offset);
+        offset * self.gain.gain(offset)
+    }
+}
+
+fn parse_params(line: &str, count: usize) -> Result<Vec<f32>, String> {
+    let nums: Vec<f32> = line.split_whitespace()
+        .skip(1)
+        .map(|s| s.parse::<f32>())
+        .collect::<Result<_, _>>()
+        .map_err(|e| format!("{}: {}", line, e))?;
+    if nums.len() != count {
+        return Err(format!("{}: expected {} parameters", line, count));
+    }
+    if nums.iter().any(|n| !n.is_finite()) {
+        return Err(format!("{}: parameters have to be finite", line));
+    }
+    Ok(nums)
+}
+
+/// Parses a curve description. The first line names the curve and gives its
+/// parameters in struct field order, e.g. `power 2.0 10.0 1.5 30.0`, except
+/// for `table` which is followed by `speed gain` lines like `PiecewiseLinear::parse`.
+pub fn parse_transfer(text: &str) -> Result<Box<dyn TransferFunction>, String> {
+    let mut lines = text.lines()
+        .map(|line| line.split('#').next().unwrap().trim())
+        .filter(|line| !line.is_empty());
+    let header = lines.next().ok_or("empty curve description")?;
+    let curve: Box<dyn TransferFunction> = match header.split_whitespace().next().unwrap() {
+        "sigmoid" => {
+            let p = parse_params(header, 6)?;
+            Box::new(Acceleration {
+                         cd_min: p[0],
+                         cd_max: p[1],
+                         v_min: p[2],
+                         v_max: p[3],
+                         lambda: p[4],
+                         ratio: p[5],
+                     })
+        }
+        "constant" => {
+            let p = parse_params(header, 1)?;
+            Box::new(ConstantGain { gain: p[0] })
+        }
+        "power" => {
+            let p = parse_params(header, 4)?;
+            Box::new(PowerLaw {
+                         scale: p[0],
+                         ref_speed: p[1],
+                         exponent: p[2],
+                         max_gain: p[3],
+                     })
+        }
+        "windows" => {
+            let p = parse_params(header, 2)?;
+            Box::new(WindowsCurve::new(p[0], p[1]))
+        }
+        "adaptive" => {
+            let p = parse_params(header, 4)?;
+            Box::new(AdaptiveProfile {
+                         base_gain: p[0],
+                         threshold: p[1],
+                         incline: p[2],
+                         max_accel: p[3],
+                     })
+        }
+        "table" => {
+            let rest: Vec<&str> = lines.collect();
+            Box::new(PiecewiseLinear::parse(&rest.join("\n"))?)
+        }
+        kind => return Err(format!("unknown curve type {}", kind)),
+    };
+    Ok(curve)
+}
+
+pub fn load_transfer(path: &Path) -> io::Result<Box<dyn TransferFunction>> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    parse_transfer(&text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_transfer_reads_each_curve_type() {
+        let power = parse_transfer("# comment\npower 2.0 10.0 1.0 30.0").unwrap();
+        assert_eq!(power.gain(20.0), 4.0);
+        assert_eq!(power.gain(1000.0), 30.0);
+
+        let constant = parse_transfer("constant 1.5").unwrap();
+        assert_eq!(constant.gain(123.0), 1.5);
+
+        let table = parse_transfer("table\n0 1\n10 3 # fast\n").unwrap();
+        assert_eq!(table.gain(5.0), 2.0);
+        assert_eq!(table.gain(20.0), 3.0);
+
+        let adaptive = parse_transfer("adaptive 2.0 10.0 0.1 1.5").unwrap();
+        assert_eq!(adaptive.gain(5.0), 2.0);
+        assert_eq!(adaptive.gain(100.0), 3.0);
+    }
+
+    #[test]
+    fn parse_transfer_rejects_bad_descriptions() {
+        assert!(parse_transfer("").is_err());
+        assert!(parse_transfer("cubic 1.0").is_err());
+        assert!(parse_transfer("power 2.0 10.0 1.0").is_err());
+        assert!(parse_transfer("sigmoid 1 2 3 4 5 x").is_err());
+        assert!(parse_transfer("sigmoid 1 2 3 4 5 NaN").is_err());
+        assert!(parse_transfer("power 2.0 inf 1.0 30.0").is_err());
+        assert!(parse_transfer("windows 1.0 -inf").is_err());
+        assert!(parse_transfer("adaptive 2.0 10.0 0.1 NaN").is_err());
+        assert!(parse_transfer("table\n0 1\n10 inf").is_err());
+    }
+
+    #[test]
+    fn rate_curve_rises_with_offset_and_levels_off() {
+        let curve = RateCurve {
+            gain: Acceleration {
+                cd_min: 10.0,
+                cd_max: 40.0,
+                v_min: 0.0,
+                v_max: 15.0,
+                lambda: 0.5,
+                ratio: 0.5,
+            },
+            max_offset: 15.0,
+        };
+        assert_eq!(curve.rate(0.0), 0.0);
+        assert_eq!(curve.rate(-3.0), 0.0);
+        let rates: Vec<f32> = (1..16).map(|offset| curve.rate(offset as f32)).collect();
+        assert!(rates.windows(2).all(|pair| pair[1] > pair[0]), "{:?}", rates);
+        assert_eq!(curve.rate(30.0), curve.rate(15.0));
+    }
+
+    #[test]
+    fn windows_curve_is_continuous_at_its_first_point() {
+        let curve = WindowsCurve::new(2.0, 3.0);
+        // the first point past the origin, in our speed units
+        let speed = 0.43 / 2.0;
+        let below = curve.gain(speed * 0.9999);
+        let at = curve.gain(speed);
+        let above = curve.gain(speed * 1.0001);
+        assert!((at - below).abs() < 1e-3 * at, "{} {}", below, at);
+        assert!((above - at).abs() < 1e-3 * at, "{} {}", at, above);
+    }
+}
@@ -1,44 +1,159 @@
-use std::f32::consts::PI;
+use std::f64::consts::PI;
 use std::f32;
 use std::collections::VecDeque;
+use std::ops::{Add, Div, Mul, Sub};
 
-use cgmath::{Vector2, vec2, InnerSpace, MetricSpace};
+use cgmath::{Vector2, Vector3, vec2, InnerSpace, MetricSpace, Zero};
 
-pub struct LowPassFilter {
+use display::{Monitor, ScreenGeometry};
+use snapshot::{Snapshot, SnapshotReader, SnapshotValue, SnapshotWriter};
+use transfer::{Acceleration, PiecewiseLinear, RateCurve, TransferFunction};
+use targets::{MagnetParams, Target, TargetMagnet};
+
+/// Scalar precision of the filters, f64 keeps long sessions accurate when
+/// time is accumulated
+pub trait FilterFloat
+    : Copy + PartialOrd + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self> + Div<Output = Self>
+    {
+    fn from_f64(x: f64) -> Self;
+}
+
+/// What the smoothing filters can work on, a scalar or a cgmath vector
+pub trait FilterSample: Copy + Add<Output = Self> + Sub<Output = Self> {
+    type Scalar: FilterFloat;
+    fn zero() -> Self;
+    fn scale(self, k: Self::Scalar) -> Self;
+    /// each component run through `f`, so every axis can get its own cutoff
+    fn map<F: Fn(Self::Scalar) -> Self::Scalar>(self, f: F) -> Self;
+    fn mul_components(self, other: Self) -> Self;
+}
+
+macro_rules! float_sample {
+    ($F:ty) => {
+        impl FilterFloat for $F {
+            fn from_f64(x: f64) -> Self {
+                x as $F
+            }
+        }
+
+        impl FilterSample for $F {
+            type Scalar = $F;
+
+            fn zero() -> Self {
+                0.0
+            }
+
+            fn scale(self, k: $F) -> Self {
+                self * k
+            }
+
+            fn map<F: Fn($F) -> $F>(self, f: F) -> Self {
+                f(self)
+            }
+
+            fn mul_components(self, other: Self) -> Self {
+                self * other
+            }
+        }
+    }
+}
+
+macro_rules! vector_sample {
+    ($V:ident, $F:ty, $($field:ident),+) => {
+        impl FilterSample for $V<$F> {
+            type Scalar = $F;
+
+            fn zero() -> Self {
+                <$V<$F> as Zero>::zero()
+            }
+
+            fn scale(self, k: $F) -> Self {
+                self * k
+            }
+
+            fn map<F: Fn($F) -> $F>(self, f: F) -> Self {
+                $V { $($field: f(self.$field)),+ }
+            }
+
+            fn mul_components(self, other: Self) -> Self {
+                $V { $($field: self.$field * other.$field),+ }
+            }
+        }
+    }
+}
+
+float_sample!(f32);
+float_sample!(f64);
+vector_sample!(Vector2, f32, x, y);
+vector_sample!(Vector2, f64, x, y);
+vector_sample!(Vector3, f32, x, y, z);
+vector_sample!(Vector3, f64, x, y, z);
+
+fn scalar<S: FilterSample>(x: f64) -> S::Scalar {
+    <S::Scalar as FilterFloat>::from_f64(x)
+}
+
+pub struct LowPassFilter<S: FilterSample> {
     first_time: bool,
-    pub hat_x_prev: f32,
+    pub hat_x_prev: S,
 }
 
-impl LowPassFilter {
-    pub fn new() -> LowPassFilter {
+impl<S: FilterSample> LowPassFilter<S> {
+    pub fn new() -> Self {
         LowPassFilter {
             first_time: true,
-            hat_x_prev: 0.0,
+            hat_x_prev: S::zero(),
+        }
+    }
+
+    pub fn filter(&mut self, x: S, alpha: S::Scalar) -> S {
+        if self.first_time {
+            self.first_time = false;
+            self.hat_x_prev = x;
         }
+        let hatx = x.scale(alpha) + self.hat_x_prev.scale(scalar::<S>(1.0) - alpha);
+        self.hat_x_prev = hatx;
+        hatx
     }
 
-    pub fn filter(&mut self, x: f32, alpha: f32) -> f32 {
+    /// Like `filter` with a separate alpha for each component
+    pub fn filter_components(&mut self, x: S, alpha: S) -> S {
         if self.first_time {
             self.first_time = false;
             self.hat_x_prev = x;
         }
-        let hatx = alpha * x + (1.0 - alpha) * self.hat_x_prev;
+        let hatx = self.hat_x_prev + (x - self.hat_x_prev).mul_components(alpha);
         self.hat_x_prev = hatx;
         hatx
     }
 }
 
-pub struct OneEuroFilter {
+impl<S: FilterSample + SnapshotValue> Snapshot for LowPassFilter<S> {
+    fn save(&self, out: &mut SnapshotWriter) {
+        out.put(self.first_time);
+        self.hat_x_prev.write_to(out);
+    }
+
+    fn restore(&mut self, input: &mut SnapshotReader) -> Result<(), String> {
+        self.first_time = input.get()?;
+        self.hat_x_prev = S::read_from(input)?;
+        Ok(())
+    }
+}
+
+/// One Euro filter, for vectors each component's cutoff adapts to its own
+/// speed so e.g. fast yaw doesn't stop pitch being smoothed.
+pub struct OneEuroFilter<S: FilterSample> {
     first_time: bool,
-    mincutoff: f32,
-    beta: f32,
-    dcutoff: f32,
-    xfilt: LowPassFilter,
-    dxfilt: LowPassFilter,
+    mincutoff: S::Scalar,
+    beta: S::Scalar,
+    dcutoff: S::Scalar,
+    xfilt: LowPassFilter<S>,
+    dxfilt: LowPassFilter<S>,
 }
 
-impl OneEuroFilter {
-    pub fn new(mincutoff: f32, beta: f32, dcutoff: f32) -> Self {
+impl<S: FilterSample> OneEuroFilter<S> {
+    pub fn new(mincutoff: S::Scalar, beta: S::Scalar, dcutoff: S::Scalar) -> Self {
         OneEuroFilter {
             first_time: true,
             mincutoff,
@@ -49,63 +164,289 @@ impl OneEuroFilter {
         }
     }
 
-    pub fn filter(&mut self, x: f32, dt: f32) -> f32 {
-        let rate = 1.0 / dt;
+    pub fn filter(&mut self, x: S, dt: S::Scalar) -> S {
+        let rate = scalar::<S>(1.0) / dt;
         let dx = if self.first_time {
             self.first_time = false;
-            0.0
+            S::zero()
         } else {
-            (x - self.xfilt.hat_x_prev) * rate
+            (x - self.xfilt.hat_x_prev).scale(rate)
         };
 
         let edx = self.dxfilt.filter(dx, Self::alpha(rate, self.dcutoff));
-        let cutoff = self.mincutoff + self.beta * edx.abs();
-        self.xfilt.filter(x, Self::alpha(rate, cutoff))
+        let (mincutoff, beta) = (self.mincutoff, self.beta);
+        let zero = scalar::<S>(0.0);
+        let alpha = edx.map(|speed| {
+            let speed = if speed < zero { zero - speed } else { speed };
+            Self::alpha(rate, mincutoff + beta * speed)
+        });
+        self.xfilt.filter_components(x, alpha)
+    }
+
+    fn alpha(rate: S::Scalar, cutoff: S::Scalar) -> S::Scalar {
+        let one = scalar::<S>(1.0);
+        let tau = one / (scalar::<S>(2.0 * PI) * cutoff);
+        let te = one / rate;
+        one / (one + (tau / te))
+    }
+}
+
+/// The speed history carries on from the snapshot but the position starts
+/// again from the next sample, the input will have moved by the time it's
+/// restored and the output shouldn't glide over from where it was.
+impl<S: FilterSample + SnapshotValue> Snapshot for OneEuroFilter<S> {
+    fn save(&self, out: &mut SnapshotWriter) {
+        out.put(self.first_time);
+        self.xfilt.save(out);
+        self.dxfilt.save(out);
+    }
+
+    fn restore(&mut self, input: &mut SnapshotReader) -> Result<(), String> {
+        input.get::<bool>()?;
+        self.xfilt.restore(input)?;
+        self.dxfilt.restore(input)?;
+        self.first_time = true;
+        self.xfilt.first_time = true;
+        Ok(())
     }
+}
+
+#[derive(Clone)]
+pub struct KalmanParams {
+    /// spectral density of the unmodelled acceleration, units²/s³
+    pub process_noise: f32,
+    /// variance of each measurement, units²
+    pub measurement_noise: f32,
+}
 
-    fn alpha(rate: f32, cutoff: f32) -> f32 {
-        let tau = 1.0 / (2.0 * PI * cutoff);
-        let te = 1.0 / rate;
-        1.0 / (1.0 + (tau / te))
+impl KalmanParams {
+    /// The same noise for positions multiplied by `scale`, e.g. to go from
+    /// mm to pixels
+    pub fn scaled(&self, scale: f32) -> Self {
+        KalmanParams {
+            process_noise: self.process_noise * scale * scale,
+            measurement_noise: self.measurement_noise * scale * scale,
+        }
     }
 }
 
-pub struct VecOneEuroFilter {
-    xf: OneEuroFilter,
-    yf: OneEuroFilter,
+/// Constant velocity Kalman filter estimating position and velocity in 2D.
+/// The axes are independent and share noise parameters so they also share
+/// a covariance matrix, which is stored as its three unique entries.
+pub struct KalmanFilter {
+    params: KalmanParams,
+    first_time: bool,
+    pos: Vector2<f32>,
+    vel: Vector2<f32>,
+    p_pos: f32,
+    p_cross: f32,
+    p_vel: f32,
 }
 
-impl VecOneEuroFilter {
-    pub fn new(mincutoff: f32, beta: f32, dcutoff: f32) -> Self {
-        VecOneEuroFilter {
-            xf: OneEuroFilter::new(mincutoff, beta, dcutoff),
-            yf: OneEuroFilter::new(mincutoff, beta, dcutoff),
+impl KalmanFilter {
+    pub fn new(params: KalmanParams) -> Self {
+        KalmanFilter {
+            params,
+            first_time: true,
+            pos: vec2(0.0, 0.0),
+            vel: vec2(0.0, 0.0),
+            p_pos: 0.0,
+            p_cross: 0.0,
+            p_vel: 0.0,
         }
     }
 
     pub fn filter(&mut self, x: Vector2<f32>, dt: f32) -> Vector2<f32> {
-        vec2(self.xf.filter(x.x, dt), self.yf.filter(x.y, dt))
+        if self.first_time {
+            self.first_time = false;
+            self.pos = x;
+            self.p_pos = self.params.measurement_noise;
+            // we know nothing about the initial velocity
+            self.p_vel = self.params.measurement_noise / (dt * dt).max(1e-6);
+            return x;
+        }
+
+        // predict
+        let q = self.params.process_noise;
+        self.pos += self.vel * dt;
+        self.p_pos += dt * (2.0 * self.p_cross + dt * self.p_vel) + q * dt * dt * dt / 3.0;
+        self.p_cross += dt * self.p_vel + q * dt * dt / 2.0;
+        self.p_vel += q * dt;
+
+        // update
+        let innovation_var = self.p_pos + self.params.measurement_noise;
+        let k_pos = self.p_pos / innovation_var;
+        let k_vel = self.p_cross / innovation_var;
+        let innovation = x - self.pos;
+        self.pos += innovation * k_pos;
+        self.vel += innovation * k_vel;
+        self.p_vel -= k_vel * self.p_cross;
+        self.p_cross -= k_vel * self.p_pos;
+        self.p_pos -= k_pos * self.p_pos;
+
+        self.pos
+    }
+
+    pub fn velocity(&self) -> Vector2<f32> {
+        self.vel
+    }
+}
+
+impl Snapshot for KalmanFilter {
+    fn save(&self, out: &mut SnapshotWriter) {
+        out.put(self.first_time);
+        out.put_vec2(self.pos);
+        out.put_vec2(self.vel);
+        out.put(self.p_pos);
+        out.put(self.p_cross);
+        out.put(self.p_vel);
+    }
+
+    fn restore(&mut self, input: &mut SnapshotReader) -> Result<(), String> {
+        self.first_time = input.get()?;
+        self.pos = input.get_vec2()?;
+        self.vel = input.get_vec2()?;
+        self.p_pos = input.get()?;
+        self.p_cross = input.get()?;
+        self.p_vel = input.get()?;
+        Ok(())
+    }
+}
+
+/// Extra scaling of accelerated head movement per axis. Pitch has a smaller
+/// comfortable range than yaw so vertical movement usually needs more gain,
+/// and looking up is often harder than looking down.
+#[derive(Clone, Debug)]
+pub struct HeadGain {
+    pub x: f32,
+    pub up: f32,
+    pub down: f32,
+}
+
+impl HeadGain {
+    pub fn apply(&self, delta: Vector2<f32>) -> Vector2<f32> {
+        let y_gain = if delta.y < 0.0 { self.up } else { self.down };
+        vec2(delta.x * self.x, delta.y * y_gain)
+    }
+}
+
+/// Records how far the user can comfortably turn their head, starting from a
+/// neutral pose looking at the centre of the screen.
+pub struct RangeOfMotionCalibration {
+    duration: f32,
+    elapsed: f32,
+    neutral: Option<Vector2<f32>>,
+    min: Vector2<f32>,
+    max: Vector2<f32>,
+}
+
+impl RangeOfMotionCalibration {
+    pub fn new(duration: f32) -> Self {
+        RangeOfMotionCalibration {
+            duration,
+            elapsed: 0.0,
+            neutral: None,
+            min: vec2(0.0, 0.0),
+            max: vec2(0.0, 0.0),
+        }
+    }
+
+    /// Returns true once enough time has passed to compute the gain
+    pub fn update(&mut self, pose: Vector2<f32>, dt: f32) -> bool {
+        if self.neutral.is_none() {
+            self.neutral = Some(pose);
+            self.min = pose;
+            self.max = pose;
+        }
+        self.min = vec2(self.min.x.min(pose.x), self.min.y.min(pose.y));
+        self.max = vec2(self.max.x.max(pose.x), self.max.y.max(pose.y));
+        self.elapsed += dt;
+        self.elapsed >= self.duration
     }
+
+    /// Vertical gain relative to horizontal such that the comfortable pitch
+    /// range covers the screen height like the yaw range covers its width
+    pub fn head_gain(&self, screen_aspect: f32) -> HeadGain {
+        let neutral = self.neutral.unwrap_or(vec2(0.0, 0.0));
+        let yaw_range = (self.max.x - self.min.x).max(f32::EPSILON);
+        // half the screen height in units of half the yaw range
+        let half_height = yaw_range / screen_aspect / 2.0;
+        let up_extent = (neutral.y - self.min.y).max(f32::EPSILON);
+        let down_extent = (self.max.y - neutral.y).max(f32::EPSILON);
+        HeadGain {
+            x: 1.0,
+            up: half_height / up_extent,
+            down: half_height / down_extent,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct DriftParams {
+    /// seconds, time constant of the long-term average the neutral pose
+    /// follows, `None` only moves it on throws and recenters
+    pub recenter_time: Option<f32>,
+    /// degrees per second, the neutral pose only follows while the head is
+    /// slower than this so pointing movements aren't eaten into
+    pub still_speed: f32,
+    /// move the neutral pose to the current one whenever a throw starts,
+    /// the head isn't steering the cursor then so nothing moves. This throws
+    /// away the drift the neutral pose has followed so far, so with frequent
+    /// throws `recenter_time` never gets long enough to take drift out.
+    pub reset_on_throw: bool,
 }
 
-/// Based on page 16 of Mathieu Nancel's "Mid-Air Pointing on Ultra-Walls" paper
-/// See the paper for how to set the constants.
-pub struct Acceleration {
-    pub cd_min: f32,
-    pub cd_max: f32,
-    pub v_min: f32,
-    pub v_max: f32,
-    pub lambda: f32,
-    pub ratio: f32,
+/// Takes head pose drift out of head movement. The neutral pose slowly
+/// follows the long-term average of the pose and the movement of the neutral
+/// pose is subtracted from the head deltas, so sensor drift and shifting in
+/// the seat don't creep the cursor and the user can keep a comfortable posture.
+/// A head turn held on purpose is taken out too, so `recenter_time` has to
+/// be long enough that this is too slow to notice.
+///
+/// The neutral pose isn't saved in snapshots, the user will be sitting
+/// differently next time, so it's seeded from the first pose.
+pub struct DriftCompensator {
+    pub params: DriftParams,
+    last_pose: Option<Vector2<f32>>,
+    neutral: Option<Vector2<f32>>,
 }
 
-impl Acceleration {
-    pub fn transform(&self, diff: f32, dt: f32) -> f32 {
-        let v_inf = self.ratio * (self.v_max - self.v_min) + self.v_min;
-        let raw_vel = diff * dt;
-        let exponent = -self.lambda * (raw_vel.abs() - v_inf);
-        let cd = ((self.cd_max - self.cd_min) / (1.0 + f32::exp(exponent))) + self.cd_min;
-        diff * cd
+impl DriftCompensator {
+    pub fn new(params: DriftParams) -> Self {
+        DriftCompensator {
+            params,
+            last_pose: None,
+            neutral: None,
+        }
+    }
+
+    /// Head movement since the last pose, relative to the neutral pose
+    pub fn delta(&mut self, pose: Vector2<f32>, head_speed: f32, dt: f32) -> Vector2<f32> {
+        let delta = match self.last_pose {
+            Some(last) => pose - last,
+            None => vec2(0.0, 0.0),
+        };
+        self.last_pose = Some(pose);
+        let neutral = self.neutral.get_or_insert(pose);
+        let drift = match self.params.recenter_time {
+            Some(recenter_time) if head_speed < self.params.still_speed => {
+                (pose - *neutral) * (dt / (recenter_time + dt))
+            }
+            _ => vec2(0.0, 0.0),
+        };
+        *neutral += drift;
+        delta - drift
+    }
+
+    /// Make the current pose the neutral one without moving anything
+    pub fn recenter(&mut self) {
+        if self.last_pose.is_some() {
+            self.neutral = self.last_pose;
+        }
+    }
+
+    pub fn recenter_at(&mut self, pose: Vector2<f32>) {
+        self.neutral = Some(pose);
     }
 }
 
@@ -130,46 +471,266 @@ impl AccumulatingRounder {
     }
 }
 
+impl Snapshot for AccumulatingRounder {
+    fn save(&self, out: &mut SnapshotWriter) {
+        out.put(self.accum);
+    }
+
+    fn restore(&mut self, input: &mut SnapshotReader) -> Result<(), String> {
+        self.accum = input.get()?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct ZoomParams {
+    pub magnification: f32,
+    /// degrees of visual angle around the centre that get magnified, grown to
+    /// the jump radius when that's bigger so whatever a throw missed is in it
+    pub radius: f32,
+    /// open the lens around the gaze point whenever a throw lands
+    pub after_throw: bool,
+}
+
+/// Gaze plus zoom: the area around the gaze point is shown magnified, by the
+/// viz-2d overlay, and inside it head movement is divided by the
+/// magnification so it moves the real cursor with correspondingly finer
+/// precision while the cursor drawn in the magnified view keeps normal speed.
+pub struct ZoomLens {
+    pub params: ZoomParams,
+    min_radius: f32,
+    radius: f32,
+    center: Option<Vector2<f32>>,
+    cursor: Vector2<f32>,
+}
+
+impl ZoomLens {
+    pub fn new(params: ZoomParams, screen: &ScreenGeometry) -> Self {
+        let min_radius = screen.deg_to_px(params.radius);
+        ZoomLens {
+            min_radius,
+            radius: min_radius,
+            params,
+            center: None,
+            cursor: vec2(0.0, 0.0),
+        }
+    }
+
+    /// Magnify the area around `center`, which should be the gaze point, and
+    /// at least `jump_radius` pixels around it so the cursor at `cursor` and
+    /// the target the gaze is near both fit in it
+    pub fn open(&mut self, center: Vector2<f32>, cursor: Vector2<i32>, jump_radius: f32) {
+        self.center = Some(center);
+        self.radius = self.min_radius.max(jump_radius);
+        self.cursor = vec2(cursor.x as f32, cursor.y as f32);
+        self.confine();
+    }
+
+    pub fn close(&mut self) {
+        self.center = None;
+    }
+
+    pub fn active(&self) -> bool {
+        self.center.is_some()
+    }
+
+    /// Move the real cursor by a head movement made in the magnified view
+    pub fn transform(&mut self, head_delta: Vector2<f32>) -> Vector2<i32> {
+        self.cursor += head_delta / self.params.magnification;
+        self.confine();
+        vec2(self.cursor.x.round() as i32, self.cursor.y.round() as i32)
+    }
+
+    /// Whether a point on screen is inside the magnified view
+    pub fn covers(&self, pt: Vector2<f32>) -> bool {
+        match self.center {
+            Some(center) => center.distance(pt) <= self.radius * self.params.magnification,
+            None => false,
+        }
+    }
+
+    /// The centre and radius of the area being magnified and where the
+    /// cursor appears inside the magnified view, for drawing
+    #[cfg(feature = "viz-2d")]
+    pub fn view(&self) -> Option<(Vector2<f32>, f32, Vector2<f32>)> {
+        self.center.map(|center| {
+            let mag = self.params.magnification;
+            (center, self.radius, center + (self.cursor - center) * mag)
+        })
+    }
+
+    fn confine(&mut self) {
+        if let Some(center) = self.center {
+            let offset = self.cursor - center;
+            if offset.magnitude() > self.radius {
+                self.cursor = center + offset.normalize() * self.radius;
+            }
+        }
+    }
+}
+
+/// Angular speed of the head in degrees per second, estimated from the raw
+/// pose so it doesn't depend on the head filter or the transfer function.
+/// The velocity is smoothed exponentially with a time constant in seconds.
+pub struct HeadSpeedEstimator {
+    smoothing: f32,
+    last_pose: Option<Vector2<f32>>,
+    vel: Vector2<f32>,
+}
+
+impl HeadSpeedEstimator {
+    pub fn new(smoothing: f32) -> Self {
+        HeadSpeedEstimator {
+            smoothing,
+            last_pose: None,
+            vel: vec2(0.0, 0.0),
+        }
+    }
+
+    pub fn update(&mut self, pose: Vector2<f32>, dt: f32) -> f32 {
+        if let Some(last) = self.last_pose {
+            if dt > 0.0 {
+                let alpha = dt / (self.smoothing + dt);
+                self.vel += ((pose - last) / dt - self.vel) * alpha;
+            }
+        }
+        self.last_pose = Some(pose);
+        self.speed()
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.vel.magnitude()
+    }
+}
+
+/// The last pose isn't kept, the head will have moved by the time it's restored
+impl Snapshot for HeadSpeedEstimator {
+    fn save(&self, out: &mut SnapshotWriter) {
+        out.put_vec2(self.vel);
+    }
+
+    fn restore(&mut self, input: &mut SnapshotReader) -> Result<(), String> {
+        self.last_pose = None;
+        self.vel = input.get_vec2()?;
+        Ok(())
+    }
+}
+
+/// Turns head movement into scrolling, in lines with sub-line precision
+/// carried over between ticks. Head speeds below `dead_zone` (degrees/s)
+/// are ignored so holding still doesn't creep.
+pub struct HeadScroll {
+    pub accel: Acceleration,
+    pub dead_zone: f32,
+    x_round: AccumulatingRounder,
+    y_round: AccumulatingRounder,
+    still_time: f32,
+}
+
+impl HeadScroll {
+    pub fn new(accel: Acceleration, dead_zone: f32) -> Self {
+        HeadScroll {
+            accel,
+            dead_zone,
+            x_round: AccumulatingRounder::new(),
+            y_round: AccumulatingRounder::new(),
+            still_time: 0.0,
+        }
+    }
+
+    pub fn transform(&mut self, head_delta: Vector2<f32>, dt: f32) -> Vector2<i32> {
+        let lines = vec2(self.axis(head_delta.x, dt), self.axis(head_delta.y, dt));
+        if lines.x == 0.0 && lines.y == 0.0 {
+            self.still_time += dt;
+        } else {
+            self.still_time = 0.0;
+        }
+        vec2(self.x_round.round(lines.x), self.y_round.round(lines.y))
+    }
+
+    /// Seconds the head has been inside the dead zone while scrolling
+    pub fn still_time(&self) -> f32 {
+        self.still_time
+    }
+
+    pub fn reset_still_time(&mut self) {
+        self.still_time = 0.0;
+    }
+
+    fn axis(&self, diff: f32, dt: f32) -> f32 {
+        if dt <= 0.0 || (diff / dt).abs() < self.dead_zone {
+            0.0
+        } else {
+            self.accel.transform(diff, dt)
+        }
+    }
+}
+
+struct Fixation {
+    sum: Vector2<f32>,
+    count: usize,
+    end_time: f64,
+}
+
+impl Fixation {
+    fn centroid(&self) -> Vector2<f32> {
+        self.sum / (self.count as f32)
+    }
+}
+
+/// Holds the gaze point on the centroid of the current fixation, detected
+/// with I-DT over the last `min_fixation_s` seconds. Fixations separated by a
+/// short gap that land close together are merged so the centroid doesn't
+/// reset on a small glitch or microsaccade.
+///
+/// Samples are windowed by the tracker's timestamps in seconds, so delays
+/// getting them to us don't change which samples count.
 pub struct FixationFilter {
-    buffer: VecDeque<Vector2<f32>>,
+    buffer: VecDeque<(f64, Vector2<f32>)>,
+    time: f64,
+    fixation: Option<Fixation>,
     pub min_fixation_s: f32,
+    /// pixels per second
     pub max_velocity: f32,
+    pub merge_gap_s: f32,
+    /// pixels
+    pub merge_distance: f32,
     pub cur: Vector2<f32>,
 }
 
 impl FixationFilter {
-    const MAX_BUFFER: usize = 128;
-
-    pub fn new(min_fixation_s: f32, max_velocity: f32) -> Self {
+    /// `max_velocity` is in degrees of visual angle per second and
+    /// `merge_distance` in degrees of visual angle
+    pub fn new(min_fixation_s: f32,
+               max_velocity: f32,
+               merge_gap_s: f32,
+               merge_distance: f32,
+               screen: &ScreenGeometry)
+               -> Self {
         FixationFilter {
             min_fixation_s,
-            max_velocity,
-            buffer: VecDeque::with_capacity(Self::MAX_BUFFER),
+            max_velocity: screen.deg_to_px(max_velocity),
+            merge_gap_s,
+            merge_distance: screen.deg_to_px(merge_distance),
+            buffer: VecDeque::new(),
+            time: 0.0,
+            fixation: None,
             cur: vec2(0.0, 0.0),
         }
     }
 
-    pub fn transform(&mut self, pt: Vector2<f32>, dt: f32) -> Vector2<f32> {
-        if self.buffer.len() >= Self::MAX_BUFFER {
+    pub fn transform(&mut self, pt: Vector2<f32>, time: f64) -> Vector2<f32> {
+        self.time = time;
+        let window_start = self.time - self.min_fixation_s as f64;
+        while self.buffer.front().map_or(false, |&(t, _)| t < window_start) {
             self.buffer.pop_front();
         }
-        self.buffer.push_back(pt);
-        let len = self.buffer.len();
+        self.buffer.push_back((self.time, pt));
 
-        if dt == 0.0 {
-            return pt;
-        }
-        let mut to_sample = (self.min_fixation_s / dt).round() as usize;
-        if to_sample > len {
-            // println!("Warning: need {:?} fixation samples but only have {}", to_sample, len);
-            to_sample = len;
-        }
-
-        // compute dispersion for to_sample by the method from the I-DT algorithm
+        // compute dispersion over the window by the method from the I-DT algorithm
         let mut min = pt;
         let mut max = pt;
-        for i in (len - to_sample)..len {
-            let el = self.buffer.get(i).unwrap();
+        for &(_, el) in &self.buffer {
             if el.x < min.x {
                 min.x = el.x;
             }
@@ -187,87 +748,512 @@ impl FixationFilter {
         let dispersion = diffs.x + diffs.y;
 
         let max_dispersion = self.max_velocity * self.min_fixation_s;
-        if dispersion < max_dispersion {
-            self.cur = pt;
+        if dispersion >= max_dispersion {
+            // not fixating, hold the last fixation
+            return self.cur;
+        }
+
+        let window_sum = self.buffer.iter().fold(vec2(0.0, 0.0), |sum, &(_, el)| sum + el);
+        let window_centroid = window_sum / (self.buffer.len() as f32);
+        let merge = match self.fixation {
+            Some(ref fix) => {
+                let first_new = self.buffer
+                    .iter()
+                    .map(|&(t, _)| t)
+                    .find(|&t| t > fix.end_time)
+                    .unwrap_or(self.time);
+                first_new - fix.end_time <= self.merge_gap_s as f64 &&
+                fix.centroid().distance(window_centroid) < self.merge_distance
+            }
+            None => false,
+        };
+
+        if !merge {
+            self.fixation = Some(Fixation {
+                                     sum: vec2(0.0, 0.0),
+                                     count: 0,
+                                     end_time: f64::NEG_INFINITY,
+                                 });
         }
+        let fix = self.fixation.as_mut().unwrap();
+        for &(t, el) in &self.buffer {
+            if t > fix.end_time {
+                fix.sum += el;
+                fix.count += 1;
+            }
+        }
+        fix.end_time = self.time;
+
+        self.cur = fix.centroid();
         self.cur
     }
 }
 
+impl Snapshot for FixationFilter {
+    fn save(&self, out: &mut SnapshotWriter) {
+        out.put(self.buffer.len());
+        for &(t, pt) in &self.buffer {
+            out.put(t);
+            out.put_vec2(pt);
+        }
+        out.put(self.time);
+        out.put_option(&self.fixation, |out, fix| {
+            out.put_vec2(fix.sum);
+            out.put(fix.count);
+            out.put(fix.end_time);
+        });
+        out.put_vec2(self.cur);
+    }
+
+    fn restore(&mut self, input: &mut SnapshotReader) -> Result<(), String> {
+        let len: usize = input.get()?;
+        self.buffer.clear();
+        for _ in 0..len {
+            self.buffer.push_back((input.get()?, input.get_vec2()?));
+        }
+        self.time = input.get()?;
+        self.fixation = input.get_option(|input| {
+                Ok(Fixation {
+                       sum: input.get_vec2()?,
+                       count: input.get()?,
+                       end_time: input.get()?,
+                   })
+            })?;
+        self.cur = input.get_vec2()?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum EyeMovement {
+    Fixation,
+    Saccade,
+    SmoothPursuit,
+    /// blinks and tracking loss
+    Loss,
+}
+
+#[derive(Clone, Debug)]
+pub enum EyeEvent {
+    Start { kind: EyeMovement, pt: Vector2<f32> },
+    End {
+        kind: EyeMovement,
+        centroid: Vector2<f32>,
+        duration: f32,
+    },
+}
+
+/// Thresholds are in degrees of visual angle and seconds, converted to pixels
+/// with the `ScreenGeometry` given to `EyeMovementClassifier::new`
 #[derive(Clone)]
-pub struct PolyMouseParams {
-    pub min_jump: f32,
-    pub speed_expand_factor: f32,
-    pub head_smoothing_factor: f32,
-    pub throw_thresh_speed: f32,
-    pub throw_speed: f32,
-    pub small_jump_factor: f32,
+pub struct EyeMovementParams {
+    /// I-VT point to point velocity above which a sample is a saccade, in
+    /// degrees of visual angle per second
+    pub saccade_velocity: f32,
+    /// I-DT dispersion below which slow samples are a fixation, otherwise
+    /// pursuit, in degrees of visual angle
+    pub max_dispersion: f32,
+    /// seconds
+    pub dispersion_window: f32,
+    /// gaps between samples longer than this many seconds are counted as a loss
+    pub max_gap: f32,
 }
 
-pub struct PolyMouseTransform {
-    params: PolyMouseParams,
-    throwing: bool,
-    smoothed_head_vel: Vector2<f32>,
-    pub last_jump_destination: Vector2<f32>,
-    x_round: AccumulatingRounder,
-    y_round: AccumulatingRounder,
+struct EyeSegment {
+    kind: EyeMovement,
+    sum: Vector2<f32>,
+    count: usize,
+    duration: f32,
 }
 
-impl PolyMouseTransform {
-    pub fn new(params: PolyMouseParams) -> Self {
-        PolyMouseTransform {
+impl EyeSegment {
+    fn centroid(&self) -> Vector2<f32> {
+        self.sum / (self.count.max(1) as f32)
+    }
+}
+
+/// Labels each gaze sample using velocity to find saccades, then dispersion
+/// over a time window to tell fixations from smooth pursuit. Emits events
+/// when a movement of one kind starts or ends.
+pub struct EyeMovementClassifier {
+    params: EyeMovementParams,
+    // params converted to pixels
+    saccade_velocity: f32,
+    max_dispersion: f32,
+    time: f64,
+    window: VecDeque<(f64, Vector2<f32>)>,
+    last_pt: Option<Vector2<f32>>,
+    current: Option<EyeSegment>,
+}
+
+impl EyeMovementClassifier {
+    pub fn new(params: EyeMovementParams, screen: &ScreenGeometry) -> Self {
+        EyeMovementClassifier {
+            saccade_velocity: screen.deg_to_px(params.saccade_velocity),
+            max_dispersion: screen.deg_to_px(params.max_dispersion),
             params,
-            throwing: false,
-            smoothed_head_vel: vec2(0.0, 0.0),
-            last_jump_destination: vec2(0.0, 0.0),
-            x_round: AccumulatingRounder::new(),
-            y_round: AccumulatingRounder::new(),
+            time: 0.0,
+            window: VecDeque::new(),
+            last_pt: None,
+            current: None,
         }
     }
 
-    pub fn transform(&mut self,
-                     gaze_pt: Vector2<f32>,
-                     mouse_pt: Vector2<i32>,
-                     head_delta: Vector2<f32>,
-                     dt: f32)
-                     -> Vector2<i32> {
-        let mouse_pt_f = vec2(mouse_pt.x as f32, mouse_pt.y as f32);
-
-        // TODO this is accelerated speed, should the acceleration be after?
-        // (px/tick) / (s/tick)
-        let head_vel = head_delta / dt;
-        self.smoothed_head_vel += (head_vel - self.smoothed_head_vel)
-            / (self.params.head_smoothing_factor / dt);
+    /// Classify a sample, `None` for an invalid one, pushing any events that happened
+    pub fn classify(&mut self,
+                    pt: Option<Vector2<f32>>,
+                    dt: f32,
+                    events: &mut Vec<EyeEvent>)
+                    -> EyeMovement {
+        self.time += dt as f64;
 
-        // println!("{:?}", self.smoothed_head_vel.magnitude());
-        if self.looking_far_away(gaze_pt, mouse_pt_f) &&
-           self.smoothed_head_vel.magnitude() > self.params.throw_thresh_speed {
-            self.throwing = true;
+        if dt > self.params.max_gap && self.last_pt.is_some() {
+            // the samples in between were dropped, count the gap as its own loss
+            let last_pt = self.last_pt.unwrap();
+            self.push_sample(EyeMovement::Loss, last_pt, dt, events);
+            self.window.clear();
+            self.last_pt = None;
         }
 
-        if self.throwing {
-            let throw_dist = self.params.throw_speed * dt;
-            let dirn = (gaze_pt - mouse_pt_f).normalize();
-
-            // check we're not jumping past the circle
-            let dest_f = if mouse_pt_f.distance(gaze_pt) > throw_dist + self.params.min_jump {
-                mouse_pt_f + dirn * throw_dist
-            } else {
-                self.last_jump_destination = gaze_pt;
-                self.throwing = false;
-                gaze_pt + dirn * (-self.params.min_jump)
-            };
+        let pt = match pt {
+            Some(pt) => pt,
+            None => {
+                let last_pt = self.last_pt.unwrap_or(vec2(0.0, 0.0));
+                self.push_sample(EyeMovement::Loss, last_pt, dt, events);
+                self.window.clear();
+                self.last_pt = None;
+                return EyeMovement::Loss;
+            }
+        };
 
-            vec2(dest_f.x as i32, dest_f.y as i32) // TODO round?
-        } else {
-            let rounded_move = vec2(self.x_round.round(head_delta.x),
-                                    self.y_round.round(head_delta.y));
-            mouse_pt + rounded_move
+        let window_start = self.time - self.params.dispersion_window as f64;
+        while self.window.front().map_or(false, |&(t, _)| t < window_start) {
+            self.window.pop_front();
         }
-    }
+        self.window.push_back((self.time, pt));
+
+        let velocity = match self.last_pt {
+            Some(last_pt) if dt > 0.0 => last_pt.distance(pt) / dt,
+            _ => 0.0,
+        };
+        let kind = if velocity > self.saccade_velocity {
+            EyeMovement::Saccade
+        } else if self.dispersion() < self.max_dispersion {
+            EyeMovement::Fixation
+        } else {
+            EyeMovement::SmoothPursuit
+        };
+        self.last_pt = Some(pt);
+        self.push_sample(kind, pt, dt, events);
+        kind
+    }
+
+    /// Centroid of the fixation in progress, if there is one
+    pub fn current_fixation(&self) -> Option<Vector2<f32>> {
+        match self.current {
+            Some(ref seg) if seg.kind == EyeMovement::Fixation => Some(seg.centroid()),
+            _ => None,
+        }
+    }
+
+    fn dispersion(&self) -> f32 {
+        let mut iter = self.window.iter().map(|&(_, pt)| pt);
+        let first = match iter.next() {
+            Some(pt) => pt,
+            None => return 0.0,
+        };
+        let (min, max) = iter.fold((first, first), |(min, max), pt| {
+            (vec2(min.x.min(pt.x), min.y.min(pt.y)), vec2(max.x.max(pt.x), max.y.max(pt.y)))
+        });
+        let diffs = max - min;
+        diffs.x + diffs.y
+    }
+
+    fn push_sample(&mut self,
+                   kind: EyeMovement,
+                   pt: Vector2<f32>,
+                   dt: f32,
+                   events: &mut Vec<EyeEvent>) {
+        if let Some(ref mut seg) = self.current {
+            if seg.kind == kind {
+                seg.sum += pt;
+                seg.count += 1;
+                seg.duration += dt;
+                return;
+            }
+        }
+
+        if let Some(seg) = self.current.take() {
+            events.push(EyeEvent::End {
+                            kind: seg.kind,
+                            centroid: seg.centroid(),
+                            duration: seg.duration,
+                        });
+        }
+        events.push(EyeEvent::Start { kind, pt });
+        self.current = Some(EyeSegment {
+                                kind,
+                                sum: pt,
+                                count: 1,
+                                duration: dt,
+                            });
+    }
+}
+
+/// How the cursor travels from where it was to the landing point of a throw
+#[derive(Clone, Debug)]
+pub enum ThrowStyle {
+    /// constant `throw_speed` in a straight line
+    Linear,
+    /// ease in and out, taking as long as `Linear` would on average
+    EaseInOut,
+    /// ease in and out over this many seconds no matter the distance
+    FixedDuration(f32),
+    /// jump straight to the landing point
+    Warp,
+}
+
+impl ThrowStyle {
+    /// `linear`, `ease_in_out`, `fixed_duration <seconds>` or `warp`
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut words = text.split_whitespace();
+        let style = match words.next()? {
+            "linear" => ThrowStyle::Linear,
+            "ease_in_out" => ThrowStyle::EaseInOut,
+            "fixed_duration" => ThrowStyle::FixedDuration(words.next()?.parse().ok()?),
+            "warp" => ThrowStyle::Warp,
+            _ => return None,
+        };
+        if words.next().is_some() {
+            return None;
+        }
+        Some(style)
+    }
+}
+
+/// Distances are in real units so they carry over between screens,
+/// `PolyMouseTransform` converts them to pixels using the `ScreenGeometry`.
+#[derive(Clone)]
+pub struct PolyMouseParams {
+    /// degrees of visual angle
+    pub min_jump: f32,
+    /// (head speed in degrees/s, degrees of visual angle added to the jump
+    /// radius) so fast head movement is more tolerant of where the gaze is
+    pub jump_expansion: Option<PiecewiseLinear>,
+    /// seconds
+    pub head_smoothing_factor: f32,
+    /// mm on screen per second
+    pub throw_thresh_speed: f32,
+    /// mm on screen per second
+    pub throw_speed: f32,
+    pub throw_style: ThrowStyle,
+    /// 0 lands short of the gaze point along the throw, 1 lands behind it
+    /// along the head's direction of motion so the head finishes the approach
+    pub head_landing_blend: f32,
+    pub small_jump_factor: f32,
+    /// estimate head velocity with a Kalman filter, in mm, instead of
+    /// exponential smoothing with `head_smoothing_factor`
+    pub head_kalman: Option<KalmanParams>,
+    /// snap throws to and slow down over targets from a `TargetProvider`
+    pub magnet: Option<MagnetParams>,
+}
+
+struct Throw {
+    start: Vector2<f32>,
+    elapsed: f32,
+}
+
+pub struct PolyMouseTransform {
+    params: PolyMouseParams,
+    // params converted to pixels
+    min_jump: f32,
+    jump_expansion: Option<PiecewiseLinear>,
+    throw_thresh_speed: f32,
+    throw_speed: f32,
+    throw: Option<Throw>,
+    throws_enabled: bool,
+    head_speed: f32,
+    smoothed_head_vel: Vector2<f32>,
+    head_pos: Vector2<f32>,
+    head_vel_filter: Option<KalmanFilter>,
+    magnet: Option<TargetMagnet>,
+    pub last_jump_destination: Vector2<f32>,
+    x_round: AccumulatingRounder,
+    y_round: AccumulatingRounder,
+}
+
+impl PolyMouseTransform {
+    pub fn new(params: PolyMouseParams, screen: &ScreenGeometry) -> Self {
+        let head_vel_filter = params.head_kalman.as_ref().map(|kalman| {
+            KalmanFilter::new(kalman.scaled(screen.mm_to_px(1.0)))
+        });
+        let magnet = params.magnet.clone().map(|magnet| TargetMagnet::new(magnet, screen));
+        let jump_expansion = params.jump_expansion.as_ref().map(|curve| {
+            PiecewiseLinear {
+                points: curve.points
+                    .iter()
+                    .map(|&(speed, extra)| (speed, screen.deg_to_px(extra)))
+                    .collect(),
+            }
+        });
+        PolyMouseTransform {
+            min_jump: screen.deg_to_px(params.min_jump),
+            jump_expansion,
+            throw_thresh_speed: screen.mm_to_px(params.throw_thresh_speed),
+            throw_speed: screen.mm_to_px(params.throw_speed),
+            params,
+            throw: None,
+            throws_enabled: true,
+            head_speed: 0.0,
+            smoothed_head_vel: vec2(0.0, 0.0),
+            head_pos: vec2(0.0, 0.0),
+            head_vel_filter,
+            magnet,
+            last_jump_destination: vec2(0.0, 0.0),
+            x_round: AccumulatingRounder::new(),
+            y_round: AccumulatingRounder::new(),
+        }
+    }
+
+    pub fn transform(&mut self,
+                     gaze_pt: Vector2<f32>,
+                     mouse_pt: Vector2<i32>,
+                     head_delta: Vector2<f32>,
+                     head_speed: f32,
+                     targets: &[Target],
+                     dt: f32)
+                     -> Vector2<i32> {
+        let mouse_pt_f = vec2(mouse_pt.x as f32, mouse_pt.y as f32);
+        self.head_speed = head_speed;
+
+        // TODO this is accelerated speed, should the acceleration be after?
+        // (px/tick) / (s/tick)
+        match self.head_vel_filter {
+            Some(ref mut filter) => {
+                self.head_pos += head_delta;
+                filter.filter(self.head_pos, dt);
+                self.smoothed_head_vel = filter.velocity();
+            }
+            None => {
+                let head_vel = head_delta / dt;
+                self.smoothed_head_vel += (head_vel - self.smoothed_head_vel)
+                    / (self.params.head_smoothing_factor / dt);
+            }
+        }
+
+        // println!("{:?}", self.smoothed_head_vel.magnitude());
+        if self.throw.is_none() && self.throws_enabled &&
+           self.looking_far_away(gaze_pt, mouse_pt_f) &&
+           self.smoothed_head_vel.magnitude() > self.throw_thresh_speed {
+            self.throw = Some(Throw {
+                                  start: mouse_pt_f,
+                                  elapsed: 0.0,
+                              });
+        }
+
+        if let Some(mut throw) = self.throw.take() {
+            throw.elapsed += dt;
+            let (dest_f, done) = match self.params.throw_style {
+                ThrowStyle::Linear => {
+                    let landing = self.landing_point(gaze_pt, mouse_pt_f, targets);
+                    let throw_dist = self.throw_speed * dt;
+                    // check we're not jumping past the circle
+                    if mouse_pt_f.distance(landing) > throw_dist {
+                        let dirn = (landing - mouse_pt_f).normalize();
+                        (mouse_pt_f + dirn * throw_dist, false)
+                    } else {
+                        (landing, true)
+                    }
+                }
+                ThrowStyle::EaseInOut => {
+                    let landing = self.landing_point(gaze_pt, throw.start, targets);
+                    let duration = throw.start.distance(landing) / self.throw_speed;
+                    Self::ease(&throw, landing, duration)
+                }
+                ThrowStyle::FixedDuration(duration) => {
+                    let landing = self.landing_point(gaze_pt, throw.start, targets);
+                    Self::ease(&throw, landing, duration)
+                }
+                ThrowStyle::Warp => (self.landing_point(gaze_pt, mouse_pt_f, targets), true),
+            };
+
+            if done {
+                self.last_jump_destination = gaze_pt;
+            } else {
+                self.throw = Some(throw);
+            }
+            vec2(dest_f.x as i32, dest_f.y as i32) // TODO round?
+        } else {
+            let head_delta = match self.magnet {
+                Some(ref magnet) => magnet.fine_move(mouse_pt_f, head_delta, targets),
+                None => head_delta,
+            };
+            let rounded_move = vec2(self.x_round.round(head_delta.x),
+                                    self.y_round.round(head_delta.y));
+            mouse_pt + rounded_move
+        }
+    }
+
+    /// Smoothstep from the start of the throw to the landing point
+    fn ease(throw: &Throw, landing: Vector2<f32>, duration: f32) -> (Vector2<f32>, bool) {
+        let t = if duration > 0.0 {
+            (throw.elapsed / duration).min(1.0)
+        } else {
+            1.0
+        };
+        let s = t * t * (3.0 - 2.0 * t);
+        (throw.start + (landing - throw.start) * s, t >= 1.0)
+    }
+
+    /// Where a throw from `from` ends, on a target near the gaze point if
+    /// there is one, otherwise `min_jump` short of the gaze point
+    fn landing_point(&self,
+                     gaze_pt: Vector2<f32>,
+                     from: Vector2<f32>,
+                     targets: &[Target])
+                     -> Vector2<f32> {
+        if let Some(target) = self.magnet.as_ref().and_then(|m| m.snap(gaze_pt, targets)) {
+            return target;
+        }
+        let throw_dirn = (gaze_pt - from).normalize();
+        let head_speed = self.smoothed_head_vel.magnitude();
+        let blend = self.params.head_landing_blend;
+        let mut dirn = throw_dirn;
+        if head_speed > 0.0 && blend > 0.0 {
+            let mixed = throw_dirn * (1.0 - blend) + (self.smoothed_head_vel / head_speed) * blend;
+            if mixed.magnitude() > 0.0 {
+                dirn = mixed.normalize();
+            }
+        }
+        gaze_pt - dirn * self.min_jump
+    }
+
+    pub fn throwing(&self) -> bool {
+        self.throw.is_some()
+    }
+
+    /// Override the jump size in pixels, e.g. from a `JumpAdapter`
+    pub fn set_jump_size(&mut self, min_jump: f32, small_jump_factor: f32) {
+        self.min_jump = min_jump;
+        self.params.small_jump_factor = small_jump_factor;
+    }
+
+    /// Throws in progress still finish, this only stops new ones starting
+    pub fn set_throws_enabled(&mut self, enabled: bool) {
+        self.throws_enabled = enabled;
+    }
+
+    /// Radius around the last jump destination within which we won't jump again
+    #[cfg(feature = "viz-2d")]
+    pub fn small_jump_radius(&self) -> f32 {
+        self.min_jump * self.params.small_jump_factor
+    }
 
     pub fn jump_radius(&self) -> f32 {
-        self.params.min_jump + self.smoothed_head_vel.magnitude() * self.params.speed_expand_factor
+        match self.jump_expansion {
+            Some(ref curve) => self.min_jump + curve.interpolate(self.head_speed),
+            None => self.min_jump,
+        }
     }
 
     fn looking_far_away(&self, gaze_pt: Vector2<f32>, mouse_pt: Vector2<f32>) -> bool {
@@ -278,40 +1264,1106 @@ impl PolyMouseTransform {
     }
 }
 
-pub struct FreezeTransformer<T: Clone> {
-    freeze_time: f32,
-    prev_mouse_down: bool,
-    time_since_down: f32,
-    frozen_val: Option<T>,
+/// Only the motion state is saved, the jump size is reset every tick anyway
+impl Snapshot for PolyMouseTransform {
+    fn save(&self, out: &mut SnapshotWriter) {
+        out.put_option(&self.throw, |out, throw| {
+            out.put_vec2(throw.start);
+            out.put(throw.elapsed);
+        });
+        out.put(self.head_speed);
+        out.put_vec2(self.smoothed_head_vel);
+        out.put_vec2(self.head_pos);
+        out.put_option(&self.head_vel_filter, |out, filter| filter.save(out));
+        out.put_vec2(self.last_jump_destination);
+        self.x_round.save(out);
+        self.y_round.save(out);
+    }
+
+    fn restore(&mut self, input: &mut SnapshotReader) -> Result<(), String> {
+        self.throw = input.get_option(|input| {
+                Ok(Throw {
+                       start: input.get_vec2()?,
+                       elapsed: input.get()?,
+                   })
+            })?;
+        self.head_speed = input.get()?;
+        self.smoothed_head_vel = input.get_vec2()?;
+        self.head_pos = input.get_vec2()?;
+        let has_filter: bool = input.get()?;
+        match self.head_vel_filter {
+            Some(ref mut filter) if has_filter => filter.restore(input)?,
+            None if !has_filter => {}
+            _ => return Err("head_kalman setting changed since the snapshot".to_owned()),
+        }
+        self.last_jump_destination = input.get_vec2()?;
+        self.x_round.restore(input)?;
+        self.y_round.restore(input)
+    }
+}
+
+#[derive(Clone)]
+pub struct JumpAdaptParams {
+    /// the tracker's screen is split into a grid with an estimate per cell
+    pub columns: usize,
+    pub rows: usize,
+    /// jump radius as a multiple of the average gaze error
+    pub radius_per_error: f32,
+    /// bounds on the jump radius in degrees of visual angle
+    pub min_jump: f32,
+    pub max_jump: f32,
+    /// small jump radius as a multiple of the average gaze error
+    pub small_jump_per_error: f32,
+    pub min_small_jump_factor: f32,
+    pub max_small_jump_factor: f32,
+    /// seconds the cursor has to stay still after the head starts correcting
+    /// a throw to count as on target
+    pub settle_time: f32,
+    /// weight of each new measurement in the running average
+    pub learning_rate: f32,
+}
+
+struct PendingThrow {
+    gaze_pt: Vector2<f32>,
+    last_pt: Vector2<i32>,
+    still_time: f32,
+    corrected: bool,
+}
+
+/// Learns how accurate the gaze is in each region of the screen from how
+/// far the head has to correct the cursor after each throw, and sizes the
+/// jump radius to match. Throws the head never corrects say nothing about the
+/// error, the landing point is just the jump radius short of the gaze point.
+pub struct JumpAdapter {
+    params: JumpAdaptParams,
+    bounds: Monitor,
+    min_jump: f32,
+    max_jump: f32,
+    errors: Vec<Option<f32>>,
+    pending: Option<PendingThrow>,
+}
+
+impl JumpAdapter {
+    pub fn new(params: JumpAdaptParams, bounds: &Monitor, screen: &ScreenGeometry) -> Self {
+        JumpAdapter {
+            min_jump: screen.deg_to_px(params.min_jump),
+            max_jump: screen.deg_to_px(params.max_jump),
+            errors: vec![None; params.columns * params.rows],
+            bounds: bounds.clone(),
+            params,
+            pending: None,
+        }
+    }
+
+    /// A throw just landed aiming for `gaze_pt`
+    pub fn throw_landed(&mut self, gaze_pt: Vector2<f32>, cursor: Vector2<i32>) {
+        self.pending = Some(PendingThrow {
+                                gaze_pt,
+                                last_pt: cursor,
+                                still_time: 0.0,
+                                corrected: false,
+                            });
+    }
+
+    pub fn throw_started(&mut self) {
+        self.pending = None;
+    }
+
+    /// Watch the correction after a throw, it's over when the user clicks or
+    /// the cursor settles, and the distance from the gaze point is the error
+    pub fn update(&mut self, cursor: Vector2<i32>, clicked: bool, dt: f32) {
+        let done = match self.pending {
+            Some(ref mut pending) => {
+                if cursor != pending.last_pt {
+                    pending.corrected = true;
+                    pending.still_time = 0.0;
+                    pending.last_pt = cursor;
+                } else if pending.corrected {
+                    pending.still_time += dt;
+                }
+                clicked || pending.still_time >= self.params.settle_time
+            }
+            None => false,
+        };
+        if !done {
+            return;
+        }
+
+        let pending = self.pending.take().unwrap();
+        if !pending.corrected {
+            return;
+        }
+        let cursor_f = vec2(cursor.x as f32, cursor.y as f32);
+        let error = pending.gaze_pt.distance(cursor_f);
+        if let Some(cell) = self.cell(pending.gaze_pt) {
+            let rate = self.params.learning_rate;
+            self.errors[cell] = Some(match self.errors[cell] {
+                                         Some(avg) => avg + (error - avg) * rate,
+                                         None => error,
+                                     });
+        }
+    }
+
+    /// Jump radius in pixels and small jump factor for a gaze point, if
+    /// there's an estimate for its region yet
+    pub fn jump_size(&self, gaze_pt: Vector2<f32>) -> Option<(f32, f32)> {
+        let error = self.cell(gaze_pt).and_then(|cell| self.errors[cell])?;
+        let radius = (error * self.params.radius_per_error).max(self.min_jump).min(self.max_jump);
+        let factor = (error * self.params.small_jump_per_error / radius)
+            .max(self.params.min_small_jump_factor)
+            .min(self.params.max_small_jump_factor);
+        Some((radius, factor))
+    }
+
+    fn cell(&self, pt: Vector2<f32>) -> Option<usize> {
+        let rel = vec2((pt.x - self.bounds.x as f32) / self.bounds.width as f32,
+                       (pt.y - self.bounds.y as f32) / self.bounds.height as f32);
+        if rel.x < 0.0 || rel.x >= 1.0 || rel.y < 0.0 || rel.y >= 1.0 {
+            return None;
+        }
+        let col = (rel.x * self.params.columns as f32) as usize;
+        let row = (rel.y * self.params.rows as f32) as usize;
+        Some(row * self.params.columns + col)
+    }
+}
+
+#[derive(Clone)]
+pub struct MagicParams {
+    /// degrees of visual angle the gaze has to be from the cursor to warp
+    pub warp_threshold: f32,
+    /// mm on screen per second of head movement that counts as starting to move
+    pub onset_speed: f32,
+}
+
+/// Zhai's MAGIC pointing: the cursor warps to where you look and the head
+/// does the rest. Liberal warps on every new fixation, conservative waits
+/// until the head starts moving.
+pub struct MagicTransform {
+    warp_threshold: f32,
+    onset_speed: f32,
+    moving: bool,
+    x_round: AccumulatingRounder,
+    y_round: AccumulatingRounder,
+}
+
+impl MagicTransform {
+    pub fn new(params: MagicParams, screen: &ScreenGeometry) -> Self {
+        MagicTransform {
+            warp_threshold: screen.deg_to_px(params.warp_threshold),
+            onset_speed: screen.mm_to_px(params.onset_speed),
+            moving: false,
+            x_round: AccumulatingRounder::new(),
+            y_round: AccumulatingRounder::new(),
+        }
+    }
+
+    pub fn liberal(&mut self,
+                   fixation_start: Option<Vector2<f32>>,
+                   mouse_pt: Vector2<i32>,
+                   head_delta: Vector2<f32>)
+                   -> Vector2<i32> {
+        match fixation_start {
+            Some(fixation) if self.far_from(fixation, mouse_pt) => round_pt(fixation),
+            _ => self.head_move(mouse_pt, head_delta),
+        }
+    }
+
+    pub fn conservative(&mut self,
+                        fixation: Option<Vector2<f32>>,
+                        mouse_pt: Vector2<i32>,
+                        head_delta: Vector2<f32>,
+                        dt: f32)
+                        -> Vector2<i32> {
+        let speed = if dt > 0.0 {
+            head_delta.magnitude() / dt
+        } else {
+            0.0
+        };
+        let onset = !self.moving && speed > self.onset_speed;
+        if speed > self.onset_speed {
+            self.moving = true;
+        } else if speed < self.onset_speed / 2.0 {
+            self.moving = false;
+        }
+
+        match fixation {
+            Some(fixation) if onset && self.far_from(fixation, mouse_pt) => round_pt(fixation),
+            _ => self.head_move(mouse_pt, head_delta),
+        }
+    }
+
+    pub fn head_move(&mut self, mouse_pt: Vector2<i32>, head_delta: Vector2<f32>) -> Vector2<i32> {
+        mouse_pt + vec2(self.x_round.round(head_delta.x), self.y_round.round(head_delta.y))
+    }
+
+    fn far_from(&self, pt: Vector2<f32>, mouse_pt: Vector2<i32>) -> bool {
+        pt.distance(vec2(mouse_pt.x as f32, mouse_pt.y as f32)) > self.warp_threshold
+    }
+}
+
+fn round_pt(pt: Vector2<f32>) -> Vector2<i32> {
+    vec2(pt.x.round() as i32, pt.y.round() as i32)
+}
+
+#[derive(Clone)]
+pub struct JoystickParams {
+    /// degrees of visual angle around the centre of the screen where
+    /// looking doesn't turn
+    pub dead_zone: f32,
+    /// speed of the motion for how far the gaze is past the dead zone
+    pub rate: RateCurve,
+    /// how much head movement adds on top, 1 moves like head pointing
+    pub head_weight: f32,
+}
+
+/// "Look to turn" for games and 3D tools: looking away from the centre of
+/// the screen moves continuously in that direction, faster the further out,
+/// and head movement adds to it for fine aiming. The output is relative
+/// motion so it's best used with a relative `PointerOutput`.
+pub struct GazeJoystick {
+    params: JoystickParams,
+    screen: ScreenGeometry,
+    centre: Vector2<f32>,
+    x_round: AccumulatingRounder,
+    y_round: AccumulatingRounder,
+}
+
+impl GazeJoystick {
+    pub fn new(params: JoystickParams, monitor: &Monitor, screen: &ScreenGeometry) -> Self {
+        GazeJoystick {
+            params,
+            screen: screen.clone(),
+            centre: vec2(monitor.x as f32 + monitor.width as f32 / 2.0,
+                         monitor.y as f32 + monitor.height as f32 / 2.0),
+            x_round: AccumulatingRounder::new(),
+            y_round: AccumulatingRounder::new(),
+        }
+    }
+
+    /// Motion in pixels for this tick from the gaze point and head movement
+    fn motion(&self, gaze_pt: Vector2<f32>, head_delta: Vector2<f32>, dt: f32) -> Vector2<f32> {
+        let mut motion = head_delta * self.params.head_weight;
+        let offset = gaze_pt - self.centre;
+        let dist = offset.magnitude();
+        let past = self.screen.px_to_deg(dist) - self.params.dead_zone;
+        if past > 0.0 {
+            let rate = self.screen.mm_to_px(self.params.rate.rate(past));
+            motion += offset / dist * rate * dt;
+        }
+        motion
+    }
+
+    pub fn transform(&mut self,
+                     gaze_pt: Vector2<f32>,
+                     mouse_pt: Vector2<i32>,
+                     head_delta: Vector2<f32>,
+                     dt: f32)
+                     -> Vector2<i32> {
+        let motion = self.motion(gaze_pt, head_delta, dt);
+        mouse_pt + vec2(self.x_round.round(motion.x), self.y_round.round(motion.y))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PointingMode {
+    PolyMouse,
+    MagicLiberal,
+    MagicConservative,
+    HeadOnly,
+    GazeOnly,
+    GazeJoystick,
+}
+
+impl PointingMode {
+    pub fn next(self) -> Self {
+        match self {
+            PointingMode::PolyMouse => PointingMode::MagicLiberal,
+            PointingMode::MagicLiberal => PointingMode::MagicConservative,
+            PointingMode::MagicConservative => PointingMode::HeadOnly,
+            PointingMode::HeadOnly => PointingMode::GazeOnly,
+            PointingMode::GazeOnly => PointingMode::GazeJoystick,
+            PointingMode::GazeJoystick => PointingMode::PolyMouse,
+        }
+    }
+}
+
+/// Everything the pointing techniques might want for one head tick
+pub struct PointingInput<'a> {
+    pub gaze_pt: Vector2<f32>,
+    /// where a fixation started since the last tick, if one did
+    pub fixation_start: Option<Vector2<f32>>,
+    /// centroid of the fixation in progress
+    pub fixation: Option<Vector2<f32>>,
+    pub mouse_pt: Vector2<i32>,
+    pub head_delta: Vector2<f32>,
+    /// angular speed of the head in degrees/s
+    pub head_speed: f32,
+    pub targets: &'a [Target],
+    pub dt: f32,
+}
+
+/// Switches between pointing techniques at runtime, keeping the state of
+/// each so switching back and forth doesn't lose anything.
+pub struct Pointer {
+    pub mode: PointingMode,
+    pub poly_mouse: PolyMouseTransform,
+    magic: MagicTransform,
+    joystick: GazeJoystick,
+}
+
+impl Pointer {
+    pub fn new(mode: PointingMode,
+               poly_mouse: PolyMouseTransform,
+               magic: MagicTransform,
+               joystick: GazeJoystick)
+               -> Self {
+        Pointer {
+            mode,
+            poly_mouse,
+            magic,
+            joystick,
+        }
+    }
+
+    pub fn transform(&mut self, input: &PointingInput) -> Vector2<i32> {
+        match self.mode {
+            PointingMode::PolyMouse => {
+                self.poly_mouse.transform(input.gaze_pt,
+                                          input.mouse_pt,
+                                          input.head_delta,
+                                          input.head_speed,
+                                          input.targets,
+                                          input.dt)
+            }
+            PointingMode::MagicLiberal => {
+                self.magic.liberal(input.fixation_start, input.mouse_pt, input.head_delta)
+            }
+            PointingMode::MagicConservative => {
+                self.magic.conservative(input.fixation, input.mouse_pt, input.head_delta, input.dt)
+            }
+            PointingMode::HeadOnly => self.magic.head_move(input.mouse_pt, input.head_delta),
+            PointingMode::GazeOnly => round_pt(input.gaze_pt),
+            PointingMode::GazeJoystick => {
+                self.joystick.transform(input.gaze_pt, input.mouse_pt, input.head_delta, input.dt)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClickType {
+    Left,
+    Right,
+    Double,
+    /// press the left button and keep it down until a `DragEnd`, another
+    /// `DragStart` or a left or double click, right clicks leave it down
+    DragStart,
+    DragEnd,
+}
+
+impl ClickType {
+    /// `left`, `right`, `double`, `drag_start` or `drag_end`
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "left" => Some(ClickType::Left),
+            "right" => Some(ClickType::Right),
+            "double" => Some(ClickType::Double),
+            "drag_start" => Some(ClickType::DragStart),
+            "drag_end" => Some(ClickType::DragEnd),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Button {
+    Left,
+    Right,
+    Middle,
+}
+
+impl Button {
+    pub const ALL: [Button; 3] = [Button::Left, Button::Right, Button::Middle];
+
+    /// Bit for this button in the pressed buttons mask from the OS
+    pub fn mask(self) -> usize {
+        match self {
+            Button::Left => 1 << 0,
+            Button::Right => 1 << 1,
+            Button::Middle => 1 << 2,
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Button::Left => 0,
+            Button::Right => 1,
+            Button::Middle => 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ButtonAction {
+    Down(Button),
+    Up(Button),
+}
+
+/// Times in seconds and distances in pixels
+#[derive(Clone)]
+pub struct ButtonParams {
+    /// hold the cursor still this long after the button goes down
+    pub press_freeze: f32,
+    /// moving further than this while frozen is a drag and unfreezes
+    pub drag_threshold: f32,
+    /// after release keep the cursor within `double_click_radius` of the
+    /// click for this long so a second click counts as a double click
+    pub double_click_time: f32,
+    pub double_click_radius: f32,
+    /// clicks we make ourselves press the button on the first click and
+    /// release it on the next, so the cursor can be moved in between
+    pub drag_lock: bool,
+}
+
+#[derive(Clone)]
+pub struct ClickParams {
+    pub left: ButtonParams,
+    pub right: ButtonParams,
+    pub middle: ButtonParams,
+}
+
+impl ClickParams {
+    pub fn button(&self, button: Button) -> &ButtonParams {
+        match button {
+            Button::Left => &self.left,
+            Button::Right => &self.right,
+            Button::Middle => &self.middle,
+        }
+    }
+}
+
+enum Hold {
+    Free,
+    /// stay at `at` unless the movement since then passes `drag_threshold`
+    Frozen {
+        at: Vector2<i32>,
+        left: f32,
+        drag_threshold: f32,
+    },
+    /// stay within `radius` of `at`
+    Confined {
+        at: Vector2<i32>,
+        left: f32,
+        radius: f32,
+    },
+}
+
+/// Stabilizes the cursor around button presses and releases, and turns the
+/// clicks we make ourselves into button actions, handling drag-lock.
+pub struct ClickStateMachine {
+    params: ClickParams,
+    prev_buttons: usize,
+    locked: [bool; 3],
+    hold: Hold,
+    // movement requested since the hold started
+    offset: Vector2<f32>,
 }
 
-impl<T: Clone> FreezeTransformer<T> {
-    pub fn new(freeze_time: f32) -> Self {
-        FreezeTransformer {
-            freeze_time,
-            prev_mouse_down: false,
-            time_since_down: 0.0,
-            frozen_val: None,
+impl ClickStateMachine {
+    pub fn new(params: ClickParams) -> Self {
+        ClickStateMachine {
+            params,
+            prev_buttons: 0,
+            locked: [false; 3],
+            hold: Hold::Free,
+            offset: vec2(0.0, 0.0),
         }
     }
 
-    pub fn transform(&mut self, val: T, mouse_down: bool, dt: f32) -> T {
-        if !self.prev_mouse_down && mouse_down {
-            self.time_since_down = 0.0;
-            self.frozen_val = Some(val.clone());
+    /// Where the cursor should go given where the pipeline wants it, the
+    /// current position and the buttons the OS says are held
+    pub fn transform(&mut self,
+                     dest: Vector2<i32>,
+                     mouse_pt: Vector2<i32>,
+                     buttons: usize,
+                     dt: f32)
+                     -> Vector2<i32> {
+        for &button in Button::ALL.iter() {
+            let was_down = self.prev_buttons & button.mask() != 0;
+            let is_down = buttons & button.mask() != 0;
+            if !was_down && is_down {
+                self.pressed(button, mouse_pt);
+            } else if was_down && !is_down {
+                self.released(button, mouse_pt);
+            }
         }
-        self.prev_mouse_down = mouse_down;
+        self.prev_buttons = buttons;
 
-        if !mouse_down {
-            return val;
+        let delta = dest - mouse_pt;
+        self.offset += vec2(delta.x as f32, delta.y as f32);
+        let (out, expired) = match self.hold {
+            Hold::Free => return dest,
+            Hold::Frozen { at, ref mut left, drag_threshold } => {
+                *left -= dt;
+                let dragging = self.offset.magnitude() > drag_threshold;
+                (at, *left <= 0.0 || dragging)
+            }
+            Hold::Confined { at, ref mut left, radius } => {
+                *left -= dt;
+                let mut offset = self.offset;
+                if offset.magnitude() > radius {
+                    offset = offset.normalize() * radius;
+                }
+                (at + vec2(offset.x.round() as i32, offset.y.round() as i32), *left <= 0.0)
+            }
+        };
+
+        if expired {
+            // let go from where we held it, replaying the movement made while
+            // held would jump the cursor all at once
+            self.hold = Hold::Free;
+            self.offset = vec2(0.0, 0.0);
         }
+        out
+    }
 
-        self.time_since_down += dt;
+    /// Button actions for a click we make ourselves at `at`. A drag start
+    /// while a drag is already going ends it, so one binding can do both.
+    pub fn click(&mut self, click: ClickType, at: Vector2<i32>) -> Vec<ButtonAction> {
+        let click = match click {
+            ClickType::DragStart if self.locked[Button::Left.index()] => ClickType::DragEnd,
+            click => click,
+        };
+        let actions = match click {
+            ClickType::Left => self.toggle_or_click(Button::Left),
+            ClickType::Right => self.toggle_or_click(Button::Right),
+            ClickType::Double => {
+                vec![ButtonAction::Down(Button::Left),
+                     ButtonAction::Up(Button::Left),
+                     ButtonAction::Down(Button::Left),
+                     ButtonAction::Up(Button::Left)]
+            }
+            ClickType::DragStart => {
+                self.locked[Button::Left.index()] = true;
+                vec![ButtonAction::Down(Button::Left)]
+            }
+            ClickType::DragEnd => {
+                self.locked[Button::Left.index()] = false;
+                vec![ButtonAction::Up(Button::Left)]
+            }
+        };
+        let button = match click {
+            ClickType::Right => Button::Right,
+            _ => Button::Left,
+        };
+        if actions.last() == Some(&ButtonAction::Down(button)) {
+            self.pressed(button, at);
+        } else {
+            self.released(button, at);
+        }
+        actions
+    }
 
-        if self.time_since_down > self.freeze_time {
-            val
+    /// Keep the cursor at `at` for a while, e.g. after warping it ourselves
+    pub fn hold(&mut self, at: Vector2<i32>, duration: f32) {
+        self.offset = vec2(0.0, 0.0);
+        self.hold = Hold::Frozen {
+            at,
+            left: duration,
+            drag_threshold: f32::INFINITY,
+        };
+    }
+
+    fn toggle_or_click(&mut self, button: Button) -> Vec<ButtonAction> {
+        if !self.params.button(button).drag_lock {
+            return vec![ButtonAction::Down(button), ButtonAction::Up(button)];
+        }
+        let locked = &mut self.locked[button.index()];
+        *locked = !*locked;
+        if *locked {
+            vec![ButtonAction::Down(button)]
         } else {
-            self.frozen_val.as_ref().unwrap().clone()
+            vec![ButtonAction::Up(button)]
+        }
+    }
+
+    fn pressed(&mut self, button: Button, at: Vector2<i32>) {
+        let params = self.params.button(button).clone();
+        // a second click lands on the first if we're still confined
+        let at = self.hold_point().unwrap_or(at);
+        self.offset = vec2(0.0, 0.0);
+        self.hold = Hold::Frozen {
+            at,
+            left: params.press_freeze,
+            drag_threshold: params.drag_threshold,
+        };
+    }
+
+    fn released(&mut self, button: Button, at: Vector2<i32>) {
+        let params = self.params.button(button).clone();
+        let at = self.hold_point().unwrap_or(at);
+        self.offset = vec2(0.0, 0.0);
+        self.hold = Hold::Confined {
+            at,
+            left: params.double_click_time,
+            radius: params.double_click_radius,
+        };
+    }
+
+    fn hold_point(&self) -> Option<Vector2<i32>> {
+        match self.hold {
+            Hold::Free => None,
+            Hold::Frozen { at, .. } |
+            Hold::Confined { at, .. } => Some(at),
+        }
+    }
+}
+
+impl Snapshot for ClickStateMachine {
+    fn save(&self, out: &mut SnapshotWriter) {
+        out.put(self.prev_buttons);
+        for &locked in &self.locked {
+            out.put(locked);
+        }
+        match self.hold {
+            Hold::Free => out.put("free"),
+            Hold::Frozen { at, left, drag_threshold } => {
+                out.put("frozen");
+                out.put(at.x);
+                out.put(at.y);
+                out.put(left);
+                out.put(drag_threshold);
+            }
+            Hold::Confined { at, left, radius } => {
+                out.put("confined");
+                out.put(at.x);
+                out.put(at.y);
+                out.put(left);
+                out.put(radius);
+            }
+        }
+        out.put_vec2(self.offset);
+    }
+
+    fn restore(&mut self, input: &mut SnapshotReader) -> Result<(), String> {
+        self.prev_buttons = input.get()?;
+        for locked in &mut self.locked {
+            *locked = input.get()?;
+        }
+        let kind: String = input.get()?;
+        self.hold = match kind.as_str() {
+            "free" => Hold::Free,
+            "frozen" => {
+                Hold::Frozen {
+                    at: vec2(input.get()?, input.get()?),
+                    left: input.get()?,
+                    drag_threshold: input.get()?,
+                }
+            }
+            "confined" => {
+                Hold::Confined {
+                    at: vec2(input.get()?, input.get()?),
+                    left: input.get()?,
+                    radius: input.get()?,
+                }
+            }
+            _ => return Err(format!("unknown hold state in snapshot: {}", kind)),
+        };
+        self.offset = input.get_vec2()?;
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DwellAction {
+    Click(ClickType),
+    /// scroll with the head until it's held still for the dwell time
+    Scroll,
+}
+
+impl DwellAction {
+    /// `click <click type>` or `scroll`
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut words = text.split_whitespace();
+        let action = match words.next()? {
+            "click" => DwellAction::Click(ClickType::parse(words.next()?)?),
+            "scroll" => DwellAction::Scroll,
+            _ => return None,
+        };
+        if words.next().is_some() {
+            return None;
+        }
+        Some(action)
+    }
+}
+
+#[derive(Clone)]
+pub struct DwellParams {
+    /// degrees of visual angle the cursor has to stay within
+    pub radius: f32,
+    /// seconds
+    pub dwell_time: f32,
+    /// seconds after a click before the next dwell starts counting
+    pub cooldown: f32,
+    pub action: DwellAction,
+}
+
+/// Clicks when the cursor stays still for a while. After a click the cursor
+/// has to leave the dwell radius before it can click again.
+pub struct DwellClicker {
+    params: DwellParams,
+    radius: f32,
+    anchor: Option<Vector2<f32>>,
+    sum: Vector2<f32>,
+    count: usize,
+    elapsed: f32,
+    cooldown_left: f32,
+    armed: bool,
+}
+
+impl DwellClicker {
+    pub fn new(params: DwellParams, screen: &ScreenGeometry) -> Self {
+        DwellClicker {
+            radius: screen.deg_to_px(params.radius),
+            params,
+            anchor: None,
+            sum: vec2(0.0, 0.0),
+            count: 0,
+            elapsed: 0.0,
+            cooldown_left: 0.0,
+            armed: true,
+        }
+    }
+
+    /// Returns the action and the centroid of the dwell to do it at when it completes
+    pub fn update(&mut self, pt: Vector2<i32>, dt: f32) -> Option<(DwellAction, Vector2<i32>)> {
+        let pt = vec2(pt.x as f32, pt.y as f32);
+        self.cooldown_left = (self.cooldown_left - dt).max(0.0);
+
+        let moved = self.anchor.map_or(true, |anchor| anchor.distance(pt) > self.radius);
+        if moved {
+            self.anchor = Some(pt);
+            self.sum = vec2(0.0, 0.0);
+            self.count = 0;
+            self.elapsed = 0.0;
+            self.armed = true;
+        }
+        if !self.armed || self.cooldown_left > 0.0 {
+            return None;
+        }
+
+        self.sum += pt;
+        self.count += 1;
+        self.elapsed += dt;
+        if self.elapsed < self.params.dwell_time {
+            return None;
+        }
+
+        let centroid = self.sum / (self.count as f32);
+        self.armed = false;
+        self.cooldown_left = self.params.cooldown;
+        Some((self.params.action, vec2(centroid.x.round() as i32, centroid.y.round() as i32)))
+    }
+
+    /// Where the current dwell is and how far along it is from 0 to 1
+    #[cfg(feature = "viz-2d")]
+    pub fn progress(&self) -> Option<(Vector2<f32>, f32)> {
+        match self.anchor {
+            Some(anchor) if self.armed && self.cooldown_left <= 0.0 => {
+                Some((anchor, (self.elapsed / self.params.dwell_time).min(1.0)))
+            }
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlinkEvent {
+    LongBlink,
+    LeftWink,
+    RightWink,
+}
+
+/// Durations in seconds. Natural blinks are shorter than `min_duration`,
+/// anything longer than `max_duration` is looking away or tracking loss.
+#[derive(Clone)]
+pub struct BlinkParams {
+    pub min_duration: f32,
+    pub max_duration: f32,
+    /// how long the open eye may drop out during a wink
+    pub wink_tolerance: f32,
+}
+
+/// Tells deliberate long blinks and winks apart from natural blinks using
+/// how long each eye was invisible to the tracker. Events fire when the
+/// eyes open again.
+pub struct BlinkDetector {
+    pub params: BlinkParams,
+    left_closed: f32,
+    right_closed: f32,
+    both_closed: f32,
+}
+
+impl BlinkDetector {
+    pub fn new(params: BlinkParams) -> Self {
+        BlinkDetector {
+            params,
+            left_closed: 0.0,
+            right_closed: 0.0,
+            both_closed: 0.0,
+        }
+    }
+
+    pub fn update(&mut self, left_valid: bool, right_valid: bool, dt: f32) -> Option<BlinkEvent> {
+        if !left_valid || !right_valid {
+            if !left_valid {
+                self.left_closed += dt;
+            }
+            if !right_valid {
+                self.right_closed += dt;
+            }
+            if !left_valid && !right_valid {
+                self.both_closed += dt;
+            }
+            return None;
+        }
+
+        let (left, right, both) = (self.left_closed, self.right_closed, self.both_closed);
+        self.left_closed = 0.0;
+        self.right_closed = 0.0;
+        self.both_closed = 0.0;
+
+        let deliberate = |t: f32| t >= self.params.min_duration && t <= self.params.max_duration;
+        let tolerance = self.params.wink_tolerance;
+        if deliberate(both) {
+            Some(BlinkEvent::LongBlink)
+        } else if deliberate(left) && right <= tolerance {
+            Some(BlinkEvent::LeftWink)
+        } else if deliberate(right) && left <= tolerance {
+            Some(BlinkEvent::RightWink)
+        } else {
+            None
+        }
+    }
+}
+
+/// What to click for each kind of deliberate blink
+#[derive(Clone)]
+pub struct BlinkBindings {
+    pub long_blink: Option<ClickType>,
+    pub left_wink: Option<ClickType>,
+    pub right_wink: Option<ClickType>,
+}
+
+impl BlinkBindings {
+    pub fn click_for(&self, event: BlinkEvent) -> Option<ClickType> {
+        match event {
+            BlinkEvent::LongBlink => self.long_blink,
+            BlinkEvent::LeftWink => self.left_wink,
+            BlinkEvent::RightWink => self.right_wink,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn adapt_params() -> JumpAdaptParams {
+        JumpAdaptParams {
+            columns: 1,
+            rows: 1,
+            radius_per_error: 1.5,
+            min_jump: 1.5,
+            max_jump: 6.0,
+            small_jump_per_error: 1.0,
+            min_small_jump_factor: 0.5,
+            max_small_jump_factor: 1.0,
+            settle_time: 0.3,
+            learning_rate: 0.2,
+        }
+    }
+
+    #[test]
+    fn kalman_filter_tracks_constant_velocity() {
+        let mut filter = KalmanFilter::new(KalmanParams {
+            process_noise: 1.0,
+            measurement_noise: 4.0,
+        });
+        let vel = vec2(30.0, -12.0);
+        let dt = 1.0 / 60.0;
+        let mut out = vec2(0.0, 0.0);
+        for i in 0..600 {
+            let t = i as f32 * dt;
+            // alternating measurement error of 2 units
+            let noise = if i % 2 == 0 { 2.0 } else { -2.0 };
+            let x = vec2(100.0, 50.0) + vel * t + vec2(noise, noise);
+            out = filter.filter(x, dt);
+        }
+        let t = 599.0 * dt;
+        let truth = vec2(100.0, 50.0) + vel * t;
+        assert!(out.distance(truth) < 1.0, "{:?} vs {:?}", out, truth);
+        assert!(filter.velocity().distance(vel) < 1.0, "{:?}", filter.velocity());
+    }
+
+    fn click_params(drag_lock: bool) -> ClickParams {
+        let button = ButtonParams {
+            press_freeze: 0.5,
+            drag_threshold: 10.0,
+            double_click_time: 0.5,
+            double_click_radius: 5.0,
+            drag_lock,
+        };
+        ClickParams {
+            left: button.clone(),
+            right: button.clone(),
+            middle: button,
+        }
+    }
+
+    /// Moves the cursor by `step` per frame for `frames` frames with
+    /// `buttons` held and returns where it was after each frame
+    fn drive(clicker: &mut ClickStateMachine,
+             mouse_pt: &mut Vector2<i32>,
+             step: Vector2<i32>,
+             buttons: usize,
+             frames: usize)
+             -> Vec<Vector2<i32>> {
+        let mut path = Vec::new();
+        for _ in 0..frames {
+            *mouse_pt = clicker.transform(*mouse_pt + step, *mouse_pt, buttons, 0.01);
+            path.push(*mouse_pt);
+        }
+        path
+    }
+
+    #[test]
+    fn press_freezes_the_cursor() {
+        let mut clicker = ClickStateMachine::new(click_params(false));
+        let mut pt = vec2(100, 100);
+        let path = drive(&mut clicker, &mut pt, vec2(1, 0), Button::Left.mask(), 5);
+        assert!(path.iter().all(|&p| p == vec2(100, 100)), "{:?}", path);
+    }
+
+    #[test]
+    fn moving_past_the_drag_threshold_releases_the_freeze() {
+        let mut clicker = ClickStateMachine::new(click_params(false));
+        let mut pt = vec2(100, 100);
+        // 3 px per frame passes the 10 px threshold on the fourth frame
+        let path = drive(&mut clicker, &mut pt, vec2(3, 0), Button::Left.mask(), 6);
+        assert_eq!(&path[..4], &[vec2(100, 100); 4]);
+        assert_eq!(path[4], vec2(103, 100));
+        assert_eq!(path[5], vec2(106, 100));
+    }
+
+    #[test]
+    fn release_confines_the_cursor_to_the_double_click_radius() {
+        let mut clicker = ClickStateMachine::new(click_params(false));
+        let mut pt = vec2(100, 100);
+        drive(&mut clicker, &mut pt, vec2(0, 0), Button::Left.mask(), 1);
+        let path = drive(&mut clicker, &mut pt, vec2(2, 0), 0, 40);
+        assert!(path.iter().all(|&p| p.x >= 100 && p.x <= 105 && p.y == 100), "{:?}", path);
+        assert_eq!(path[39], vec2(105, 100));
+        // free again once the double click time is over
+        let path = drive(&mut clicker, &mut pt, vec2(2, 0), 0, 20);
+        assert!(path[19].x > 105);
+        assert_eq!(path[19] - path[18], vec2(2, 0));
+    }
+
+    #[test]
+    fn drag_lock_toggles_the_button() {
+        let mut clicker = ClickStateMachine::new(click_params(true));
+        let at = vec2(100, 100);
+        assert_eq!(clicker.click(ClickType::Left, at), vec![ButtonAction::Down(Button::Left)]);
+        assert_eq!(clicker.click(ClickType::Left, at), vec![ButtonAction::Up(Button::Left)]);
+        assert_eq!(clicker.click(ClickType::Right, at), vec![ButtonAction::Down(Button::Right)]);
+        assert_eq!(clicker.click(ClickType::Right, at), vec![ButtonAction::Up(Button::Right)]);
+
+        let mut clicker = ClickStateMachine::new(click_params(false));
+        assert_eq!(clicker.click(ClickType::Left, at),
+                   vec![ButtonAction::Down(Button::Left), ButtonAction::Up(Button::Left)]);
+        // a drag start while dragging ends the drag
+        assert_eq!(clicker.click(ClickType::DragStart, at), vec![ButtonAction::Down(Button::Left)]);
+        assert_eq!(clicker.click(ClickType::DragStart, at), vec![ButtonAction::Up(Button::Left)]);
+    }
+
+    #[test]
+    fn zoom_lens_reaches_the_gaze_point_from_the_landing_point() {
+        let monitor = Monitor::new(0, 0, 1920, 1080);
+        let screen = ScreenGeometry::new(&monitor, 500.0, 600.0);
+        let mut zoom = ZoomLens::new(ZoomParams {
+                                         magnification: 4.0,
+                                         radius: 1.0,
+                                         after_throw: true,
+                                     },
+                                     &screen);
+        let jump_radius = screen.deg_to_px(2.5);
+        let gaze = vec2(960.0, 540.0);
+        // a throw that undershot by most of the jump radius
+        let landed = vec2(960 - (jump_radius * 0.9) as i32, 540);
+        zoom.open(gaze, landed, jump_radius);
+        assert!(zoom.covers(gaze));
+        // the landing point is inside the lens so opening doesn't move it
+        assert_eq!(zoom.transform(vec2(0.0, 0.0)), landed);
+        let mut pt = landed;
+        for _ in 0..100 {
+            pt = zoom.transform(vec2(jump_radius * 0.1, 0.0));
+            if pt.x >= 960 {
+                break;
+            }
+        }
+        assert!(pt.x >= 960, "{:?}", pt);
+    }
+
+    #[test]
+    fn drift_compensation_cancels_a_slow_ramp() {
+        let mut drift = DriftCompensator::new(DriftParams {
+            recenter_time: Some(10.0),
+            still_speed: 2.0,
+            reset_on_throw: false,
+        });
+        // the head is still but the tracker reports it turning slowly
+        let rate = vec2(0.05, -0.02); // degrees per second
+        let dt = 0.01;
+        let mut delta = vec2(0.0, 0.0);
+        for i in 0..10_000 {
+            let pose = rate * (i as f32 * dt);
+            delta = drift.delta(pose, rate.magnitude(), dt);
+        }
+        let ramp_step = (rate * dt).magnitude();
+        assert!(delta.magnitude() < 0.01 * ramp_step, "{:?}", delta);
+    }
+
+    #[test]
+    fn uncorrected_throws_keep_the_minimum_jump() {
+        let monitor = Monitor::new(0, 0, 1920, 1080);
+        let screen = ScreenGeometry::new(&monitor, 500.0, 600.0);
+        let mut adapter = JumpAdapter::new(adapt_params(), &monitor, &screen);
+        let min_jump = screen.deg_to_px(1.5);
+        let gaze = vec2(960.0, 540.0);
+
+        // one small correction so there's an estimate to watch
+        adapter.throw_started();
+        adapter.throw_landed(gaze, vec2(950, 540));
+        adapter.update(vec2(955, 540), false, 0.01);
+        for _ in 0..50 {
+            adapter.update(vec2(955, 540), false, 0.01);
+        }
+        let (radius, _) = adapter.jump_size(gaze).unwrap();
+        assert_eq!(radius, min_jump);
+
+        // throws land the jump radius short of the gaze point, and without a
+        // correction the user either waits or clicks right there
+        for i in 0..20 {
+            let landing = vec2((gaze.x - radius).round() as i32, gaze.y as i32);
+            adapter.throw_started();
+            adapter.throw_landed(gaze, landing);
+            for _ in 0..100 {
+                adapter.update(landing, false, 0.01);
+            }
+            adapter.update(landing, i % 2 == 0, 0.01);
+            let (radius, _) = adapter.jump_size(gaze).unwrap();
+            assert_eq!(radius, min_jump);
         }
     }
 }
@@ -0,0 +1,425 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::mem;
+use std::os::raw::{c_char, c_int, c_long, c_ulong};
+use std::os::unix::io::AsRawFd;
+use std::path::PathBuf;
+use std::slice;
+
+use cgmath::{vec2, Vector2};
+
+use display::DisplayLayout;
+use output::PointerOutput;
+use transforms::{Button, ButtonAction};
+
+extern "C" {
+    fn ioctl(fd: c_int, request: c_ulong, ...) -> c_int;
+}
+
+// from linux/uinput.h and linux/input-event-codes.h
+const UI_DEV_CREATE: c_ulong = 0x5501;
+const UI_DEV_DESTROY: c_ulong = 0x5502;
+const UI_SET_EVBIT: c_ulong = 0x4004_5564;
+const UI_SET_KEYBIT: c_ulong = 0x4004_5565;
+const UI_SET_RELBIT: c_ulong = 0x4004_5566;
+const UI_SET_ABSBIT: c_ulong = 0x4004_5567;
+// UI_GET_SYSNAME(64)
+const UI_GET_SYSNAME: c_ulong = 0x8040_552c;
+// EVIOCGBIT(EV_KEY, KEY_BYTES) and EVIOCGKEY(KEY_BYTES) from linux/input.h
+const EVIOCGBIT_KEY: c_ulong = 0x8060_4521;
+const EVIOCGKEY: c_ulong = 0x8060_4518;
+const KEY_BYTES: usize = 0x60;
+
+const BUS_VIRTUAL: u16 = 0x06;
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const EV_ABS: u16 = 0x03;
+const SYN_REPORT: u16 = 0;
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+const BTN_MIDDLE: u16 = 0x112;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const REL_HWHEEL: u16 = 0x06;
+const REL_WHEEL: u16 = 0x08;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_CNT: usize = 0x40;
+
+#[repr(C)]
+struct InputId {
+    bustype: u16,
+    vendor: u16,
+    product: u16,
+    version: u16,
+}
+
+#[repr(C)]
+struct UinputUserDev {
+    name: [c_char; 80],
+    id: InputId,
+    ff_effects_max: u32,
+    absmax: [i32; ABS_CNT],
+    absmin: [i32; ABS_CNT],
+    absfuzz: [i32; ABS_CNT],
+    absflat: [i32; ABS_CNT],
+}
+
+#[repr(C)]
+struct InputEvent {
+    tv_sec: c_long,
+    tv_usec: c_long,
+    kind: u16,
+    code: u16,
+    value: i32,
+}
+
+unsafe fn as_bytes<T>(value: &T) -> &[u8] {
+    slice::from_raw_parts(value as *const T as *const u8, mem::size_of::<T>())
+}
+
+fn check(ret: c_int) -> io::Result<()> {
+    if ret < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+fn key_bit(bits: &[u8; KEY_BYTES], code: u16) -> bool {
+    bits[code as usize / 8] & (1 << (code % 8)) != 0
+}
+
+/// The evdev nodes of the real pointing devices, so the buttons held on
+/// them can be read back. Devices we can't read are skipped.
+fn physical_pointers() -> Vec<File> {
+    let entries = match fs::read_dir("/dev/input") {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut pointers = Vec::new();
+    for entry in entries.filter_map(|entry| entry.ok()) {
+        if !entry.file_name().to_string_lossy().starts_with("event") {
+            continue;
+        }
+        let file = match File::open(entry.path()) {
+            Ok(file) => file,
+            Err(_) => continue,
+        };
+        let mut bits = [0u8; KEY_BYTES];
+        let ret = unsafe { ioctl(file.as_raw_fd(), EVIOCGBIT_KEY, bits.as_mut_ptr()) };
+        if ret >= 0 && key_bit(&bits, BTN_LEFT) {
+            pointers.push(file);
+        }
+    }
+    pointers
+}
+
+fn uinput_button(button: Button) -> u16 {
+    match button {
+        Button::Left => BTN_LEFT,
+        Button::Right => BTN_RIGHT,
+        Button::Middle => BTN_MIDDLE,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum UinputMode {
+    /// a mouse sending movement, the compositor's pointer acceleration
+    /// applies to it so it's best used with a flat profile
+    Relative,
+    /// a tablet sending positions on the whole desktop
+    Absolute,
+}
+
+impl UinputMode {
+    /// `relative` or `absolute`
+    pub fn parse(text: &str) -> Option<Self> {
+        match text {
+            "relative" => Some(UinputMode::Relative),
+            "absolute" => Some(UinputMode::Absolute),
+            _ => None,
+        }
+    }
+}
+
+/// A virtual pointing device made with uinput, for Wayland compositors that
+/// don't let clients move the cursor. The cursor position can't be read back
+/// so this remembers where it sent it. Buttons held on real mice are read
+/// from their evdev nodes, which needs read access to /dev/input, without it
+/// only our own buttons are seen and pressing a real one doesn't freeze the
+/// cursor for the click.
+pub struct UinputOutput {
+    file: File,
+    mode: UinputMode,
+    pos: Vector2<i32>,
+    buttons: usize,
+    pointers: Vec<File>,
+}
+
+impl UinputOutput {
+    pub fn new(mode: UinputMode, layout: &DisplayLayout) -> io::Result<Self> {
+        // before ours exists so it isn't one of them
+        let pointers = physical_pointers();
+        if pointers.is_empty() {
+            println!("Can't read any mice in /dev/input, clicks on them won't be seen");
+        }
+        let file = OpenOptions::new().write(true).open("/dev/uinput")?;
+        let fd = file.as_raw_fd();
+        unsafe {
+            check(ioctl(fd, UI_SET_EVBIT, EV_KEY as c_int))?;
+            for &code in &[BTN_LEFT, BTN_RIGHT, BTN_MIDDLE] {
+                check(ioctl(fd, UI_SET_KEYBIT, code as c_int))?;
+            }
+            check(ioctl(fd, UI_SET_EVBIT, EV_REL as c_int))?;
+            check(ioctl(fd, UI_SET_RELBIT, REL_WHEEL as c_int))?;
+            check(ioctl(fd, UI_SET_RELBIT, REL_HWHEEL as c_int))?;
+            match mode {
+                UinputMode::Relative => {
+                    check(ioctl(fd, UI_SET_RELBIT, REL_X as c_int))?;
+                    check(ioctl(fd, UI_SET_RELBIT, REL_Y as c_int))?;
+                }
+                UinputMode::Absolute => {
+                    check(ioctl(fd, UI_SET_EVBIT, EV_ABS as c_int))?;
+                    check(ioctl(fd, UI_SET_ABSBIT, ABS_X as c_int))?;
+                    check(ioctl(fd, UI_SET_ABSBIT, ABS_Y as c_int))?;
+                }
+            }
+        }
+
+        let mut dev: UinputUserDev = unsafe { mem::zeroed() };
+        for (dst, &src) in dev.name.iter_mut().zip(b"fusion_mouse".iter()) {
+            *dst = src as c_char;
+        }
+        dev.id = InputId {
+            bustype: BUS_VIRTUAL,
+            vendor: 0,
+            product: 0,
+            version: 1,
+        };
+        // the compositor maps the axes onto the whole desktop
+        let min_x = layout.monitors.iter().map(|mon| mon.x).min().unwrap_or(0);
+        let min_y = layout.monitors.iter().map(|mon| mon.y).min().unwrap_or(0);
+        let max_x = layout.monitors.iter().map(|mon| mon.x + mon.width).max().unwrap_or(1);
+        let max_y = layout.monitors.iter().map(|mon| mon.y + mon.height).max().unwrap_or(1);
+        dev.absmin[ABS_X as usize] = min_x;
+        dev.absmax[ABS_X as usize] = max_x - 1;
+        dev.absmin[ABS_Y as usize] = min_y;
+        dev.absmax[ABS_Y as usize] = max_y - 1;
+        (&file).write_all(unsafe { as_bytes(&dev) })?;
+        unsafe {
+            check(ioctl(fd, UI_DEV_CREATE))?;
+        }
+
+        let mut output = UinputOutput {
+            file,
+            mode,
+            pos: vec2(min_x, min_y),
+            buttons: 0,
+            pointers,
+        };
+        if mode == UinputMode::Relative {
+            // we don't know where the cursor starts, so push it into the top
+            // left corner where the compositor stops it
+            let far = (max_x - min_x).max(max_y - min_y) * 2;
+            output.send(&[(EV_REL, REL_X, -far), (EV_REL, REL_Y, -far)]);
+        }
+        let tracker = layout.tracker();
+        output.move_to(vec2(tracker.x + tracker.width / 2, tracker.y + tracker.height / 2));
+        Ok(output)
+    }
+
+    /// The evdev node of the device, to read back what it sends e.g. with evtest
+    pub fn event_path(&self) -> io::Result<PathBuf> {
+        let mut sysname = [0u8; 64];
+        unsafe {
+            check(ioctl(self.file.as_raw_fd(), UI_GET_SYSNAME, sysname.as_mut_ptr()))?;
+        }
+        let len = sysname.iter().position(|&c| c == 0).unwrap_or(sysname.len());
+        let dir = PathBuf::from("/sys/devices/virtual/input")
+            .join(String::from_utf8_lossy(&sysname[..len]).into_owned());
+        for entry in fs::read_dir(&dir)? {
+            let name = entry?.file_name().to_string_lossy().into_owned();
+            if name.starts_with("event") {
+                return Ok(PathBuf::from("/dev/input").join(name));
+            }
+        }
+        Err(io::Error::new(io::ErrorKind::NotFound,
+                           format!("no event device in {}", dir.display())))
+    }
+
+    /// Writes `(type, code, value)` events followed by a sync
+    fn send(&mut self, events: &[(u16, u16, i32)]) {
+        let mut bytes = Vec::new();
+        for &(kind, code, value) in events.iter().chain(&[(EV_SYN, SYN_REPORT, 0)]) {
+            let event = InputEvent {
+                tv_sec: 0,
+                tv_usec: 0,
+                kind,
+                code,
+                value,
+            };
+            bytes.extend_from_slice(unsafe { as_bytes(&event) });
+        }
+        if let Err(e) = self.file.write_all(&bytes) {
+            println!("Error writing to uinput: {}", e);
+        }
+    }
+}
+
+impl PointerOutput for UinputOutput {
+    fn move_to(&mut self, pt: Vector2<i32>) {
+        match self.mode {
+            UinputMode::Relative => {
+                let delta = pt - self.pos;
+                if delta.x != 0 || delta.y != 0 {
+                    self.send(&[(EV_REL, REL_X, delta.x), (EV_REL, REL_Y, delta.y)]);
+                }
+            }
+            UinputMode::Absolute => self.send(&[(EV_ABS, ABS_X, pt.x), (EV_ABS, ABS_Y, pt.y)]),
+        }
+        self.pos = pt;
+    }
+
+    fn button(&mut self, action: ButtonAction) {
+        match action {
+            ButtonAction::Down(button) => {
+                self.buttons |= button.mask();
+                self.send(&[(EV_KEY, uinput_button(button), 1)]);
+            }
+            ButtonAction::Up(button) => {
+                self.buttons &= !button.mask();
+                self.send(&[(EV_KEY, uinput_button(button), 0)]);
+            }
+        }
+    }
+
+    fn scroll(&mut self, amount: Vector2<i32>) {
+        let mut events = Vec::new();
+        if amount.x != 0 {
+            events.push((EV_REL, REL_HWHEEL, amount.x));
+        }
+        if amount.y != 0 {
+            // the wheel counts up as positive
+            events.push((EV_REL, REL_WHEEL, -amount.y));
+        }
+        if !events.is_empty() {
+            self.send(&events);
+        }
+    }
+
+    fn location(&self) -> Vector2<i32> {
+        self.pos
+    }
+
+    fn pressed_buttons(&self) -> usize {
+        let mut buttons = self.buttons;
+        for pointer in &self.pointers {
+            let mut bits = [0u8; KEY_BYTES];
+            if unsafe { ioctl(pointer.as_raw_fd(), EVIOCGKEY, bits.as_mut_ptr()) } < 0 {
+                continue;
+            }
+            for &button in Button::ALL.iter() {
+                if key_bit(&bits, uinput_button(button)) {
+                    buttons |= button.mask();
+                }
+            }
+        }
+        buttons
+    }
+}
+
+impl Drop for UinputOutput {
+    fn drop(&mut self) {
+        unsafe {
+            ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::thread;
+    use std::time::Duration;
+
+    use display::Monitor;
+
+    const O_NONBLOCK: c_int = 0o4000;
+
+    fn layout() -> DisplayLayout {
+        DisplayLayout {
+            monitors: vec![Monitor::new(0, 0, 1920, 1080)],
+            tracker_monitor: 0,
+            regions: Vec::new(),
+        }
+    }
+
+    /// Opens the device's evdev node, waiting for udev to make it
+    fn open_events(output: &UinputOutput) -> File {
+        for _ in 0..50 {
+            if let Ok(path) = output.event_path() {
+                if let Ok(file) = OpenOptions::new().read(true).custom_flags(O_NONBLOCK).open(path) {
+                    return file;
+                }
+            }
+            thread::sleep(Duration::from_millis(20));
+        }
+        panic!("no evdev node for the uinput device");
+    }
+
+    /// `(type, code, value)` of everything queued on the node
+    fn read_events(file: &mut File) -> Vec<(u16, u16, i32)> {
+        let mut events = Vec::new();
+        let mut event: InputEvent = unsafe { mem::zeroed() };
+        loop {
+            let bytes = unsafe {
+                slice::from_raw_parts_mut(&mut event as *mut InputEvent as *mut u8,
+                                          mem::size_of::<InputEvent>())
+            };
+            match file.read_exact(bytes) {
+                Ok(()) => events.push((event.kind, event.code, event.value)),
+                Err(_) => return events,
+            }
+        }
+    }
+
+    fn check_device(mode: UinputMode) -> Vec<(u16, u16, i32)> {
+        let mut output = UinputOutput::new(mode, &layout())
+            .expect("needs write access to /dev/uinput");
+        let mut events = open_events(&output);
+        read_events(&mut events);
+
+        let start = output.location();
+        output.move_to(start + vec2(10, -5));
+        output.button(ButtonAction::Down(Button::Left));
+        output.button(ButtonAction::Up(Button::Left));
+        output.scroll(vec2(0, 2));
+        thread::sleep(Duration::from_millis(50));
+
+        let events = read_events(&mut events);
+        assert!(events.contains(&(EV_KEY, BTN_LEFT, 1)), "{:?}", events);
+        assert!(events.contains(&(EV_KEY, BTN_LEFT, 0)), "{:?}", events);
+        assert!(events.contains(&(EV_REL, REL_WHEEL, -2)), "{:?}", events);
+        events
+    }
+
+    #[test]
+    #[ignore]
+    fn relative_device_sends_motion_clicks_and_scrolling() {
+        let events = check_device(UinputMode::Relative);
+        assert!(events.contains(&(EV_REL, REL_X, 10)), "{:?}", events);
+        assert!(events.contains(&(EV_REL, REL_Y, -5)), "{:?}", events);
+    }
+
+    #[test]
+    #[ignore]
+    fn absolute_device_sends_positions_clicks_and_scrolling() {
+        let events = check_device(UinputMode::Absolute);
+        assert!(events.contains(&(EV_ABS, ABS_X, 970)), "{:?}", events);
+        assert!(events.contains(&(EV_ABS, ABS_Y, 535)), "{:?}", events);
+    }
+}
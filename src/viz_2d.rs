@@ -1,3 +1,5 @@
+use std::os::raw::c_void;
+use std::slice;
 use std::sync::mpsc;
 
 use cgmath::{self, Vector2};
@@ -8,10 +10,42 @@ use glium::glutin::os::macos::WindowExt;
 use objc::runtime::{YES, NO};
 use cocoa::base::{id, nil};
 use cocoa::appkit::{self, NSWindow, NSWindowStyleMask};
+use cocoa::foundation::{NSPoint, NSRect, NSSize};
 
 use signpost;
 
-unsafe fn make_fullscreen_overlay(window: &glutin::GlWindow) {
+type CGImageRef = *mut c_void;
+type CGDataProviderRef = *mut c_void;
+type CFDataRef = *const c_void;
+
+const K_CG_WINDOW_LIST_OPTION_ON_SCREEN_BELOW_WINDOW: u32 = 1 << 2;
+const K_CG_WINDOW_IMAGE_DEFAULT: u32 = 0;
+
+#[link(name = "CoreGraphics", kind = "framework")]
+extern "C" {
+    fn CGWindowListCreateImage(bounds: NSRect,
+                               list_option: u32,
+                               window_id: u32,
+                               image_option: u32)
+                               -> CGImageRef;
+    fn CGImageGetWidth(image: CGImageRef) -> usize;
+    fn CGImageGetHeight(image: CGImageRef) -> usize;
+    fn CGImageGetBytesPerRow(image: CGImageRef) -> usize;
+    fn CGImageGetBitsPerPixel(image: CGImageRef) -> usize;
+    fn CGImageGetDataProvider(image: CGImageRef) -> CGDataProviderRef;
+    fn CGDataProviderCopyData(provider: CGDataProviderRef) -> CFDataRef;
+    fn CGImageRelease(image: CGImageRef);
+}
+
+#[link(name = "CoreFoundation", kind = "framework")]
+extern "C" {
+    fn CFDataGetBytePtr(data: CFDataRef) -> *const u8;
+    fn CFDataGetLength(data: CFDataRef) -> isize;
+    fn CFRelease(cf: *const c_void);
+}
+
+/// Returns the window number, to capture what's below the overlay
+unsafe fn make_fullscreen_overlay(window: &glutin::GlWindow) -> u32 {
     println!("Making window transparent");
     let os_window = window.get_nswindow() as id;
     os_window.setIgnoresMouseEvents_(YES);
@@ -20,6 +54,43 @@ unsafe fn make_fullscreen_overlay(window: &glutin::GlWindow) {
     os_window.setStyleMask_(NSWindowStyleMask::NSBorderlessWindowMask);
     let main_frame = appkit::NSScreen::mainScreen(nil).frame();
     os_window.setFrame_display_(main_frame, YES);
+    let window_number: i64 = msg_send![os_window, windowNumber];
+    window_number as u32
+}
+
+/// What's on screen below our overlay inside `rect`, in desktop points.
+/// Screen captures come as BGRA rows from the top, textures want RGBA rows
+/// from the bottom.
+unsafe fn capture_below(display: &glium::Display,
+                        window_id: u32,
+                        rect: NSRect)
+                        -> Option<glium::texture::Texture2d> {
+    let image = CGWindowListCreateImage(rect,
+                                        K_CG_WINDOW_LIST_OPTION_ON_SCREEN_BELOW_WINDOW,
+                                        window_id,
+                                        K_CG_WINDOW_IMAGE_DEFAULT);
+    if image.is_null() {
+        return None;
+    }
+    let width = CGImageGetWidth(image);
+    let height = CGImageGetHeight(image);
+    let stride = CGImageGetBytesPerRow(image);
+    if CGImageGetBitsPerPixel(image) != 32 || width == 0 || height == 0 {
+        CGImageRelease(image);
+        return None;
+    }
+    let data = CGDataProviderCopyData(CGImageGetDataProvider(image));
+    let bytes = slice::from_raw_parts(CFDataGetBytePtr(data), CFDataGetLength(data) as usize);
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    for row in (0..height).rev() {
+        for px in bytes[row * stride..row * stride + width * 4].chunks(4) {
+            rgba.extend_from_slice(&[px[2], px[1], px[0], 255]);
+        }
+    }
+    CFRelease(data);
+    CGImageRelease(image);
+    let raw = glium::texture::RawImage2d::from_raw_rgba(rgba, (width as u32, height as u32));
+    glium::texture::Texture2d::new(display, raw).ok()
 }
 
 #[derive(Copy,Clone)]
@@ -30,10 +101,20 @@ pub struct DebugPoint {
 }
 implement_vertex!(DebugPoint, offset, color, size);
 
+/// A circle of the screen shown magnified around its own centre
+#[derive(Copy,Clone)]
+pub struct Lens {
+    pub center: [f32; 2],
+    /// of the area being magnified
+    pub radius: f32,
+    pub magnification: f32,
+}
+
 pub struct DebugFrame {
     pub points: Vec<DebugPoint>,
     pub display_width: f32,
     pub display_height: f32,
+    pub lens: Option<Lens>,
 }
 
 impl DebugFrame {
@@ -89,7 +170,7 @@ impl DebugWindow {
         let context = glutin::ContextBuilder::new().with_vsync(false);
         let display = glium::Display::new(window, context, &events_loop).unwrap();
 
-        unsafe { make_fullscreen_overlay(&display.gl_window()) }
+        let window_id = unsafe { make_fullscreen_overlay(&display.gl_window()) };
 
         // building the vertex buffer, which contains all the vertices that we will draw
         let vertex_buffer = {
@@ -154,6 +235,46 @@ impl DebugWindow {
         )
                 .unwrap();
 
+        // the magnified screen capture, as a circle
+        let lens_program = program!(&display,
+            140 => {
+                vertex: "
+                    #version 140
+                    uniform mat4 matrix;
+                    uniform vec2 center;
+                    uniform float size;
+                    in vec2 position;
+
+                    out vec2 vPosition;
+                    out vec2 vTexCoord;
+                    void main() {
+                        gl_Position = matrix * vec4(position*size + center, 0.0, 1.0);
+                        vPosition = position;
+                        // screen y points down, texture rows go up
+                        vTexCoord = vec2(position.x + 0.5, 0.5 - position.y);
+                    }
+                ",
+
+                fragment: "
+                    #version 140
+                    uniform sampler2D tex;
+                    in vec2 vPosition;
+                    in vec2 vTexCoord;
+                    out vec4 f_color;
+                    void main() {
+                        float dist = dot(vPosition,vPosition);
+                        if(dist > 0.25) discard;
+                        if(dist > 0.245) {
+                            f_color = vec4(1.0, 1.0, 1.0, 1.0);
+                        } else {
+                            f_color = texture(tex, vTexCoord);
+                        }
+                    }
+                "
+            },
+        )
+                .unwrap();
+
         // Here we draw the black background and triangle to the screen using the previously
         // initialised resources.
         //
@@ -180,6 +301,27 @@ impl DebugWindow {
                 blend: glium::Blend::alpha_blending(),
                 .. Default::default()
             };
+            if let Some(lens) = frame.lens {
+                let rect = NSRect::new(NSPoint::new((lens.center[0] - lens.radius) as f64,
+                                                    (lens.center[1] - lens.radius) as f64),
+                                       NSSize::new(lens.radius as f64 * 2.0,
+                                                   lens.radius as f64 * 2.0));
+                if let Some(texture) = unsafe { capture_below(&display, window_id, rect) } {
+                    let lens_uniforms = uniform! {
+                        matrix: matrix,
+                        center: lens.center,
+                        size: lens.radius * lens.magnification * 2.0,
+                        tex: &texture,
+                    };
+                    target
+                        .draw(&vertex_buffer,
+                              &index_buffer,
+                              &lens_program,
+                              &lens_uniforms,
+                              &params)
+                        .unwrap();
+                }
+            }
             target
                 .draw((&vertex_buffer, per_instance.per_instance().unwrap()),
                       &index_buffer,